@@ -0,0 +1,82 @@
+use image::{Delay, DynamicImage, Frame};
+use image::gif::GifEncoder;
+use std::fs::{self, File};
+use std::io::{BufWriter, Error};
+use std::path::Path;
+use crate::heatmap::Heatmap;
+use crate::markers::{self, Marker};
+use crate::projection::CellToPixel;
+
+/// How the orders of a game are spread over the frames of an animation, and how they are drawn.
+pub struct Settings {
+    /// Game frames covered by each animation frame.
+    pub window: i32,
+    /// Over how many windows an order fades out.
+    pub fade: i32,
+    /// How long each animation frame is shown, in milliseconds.
+    pub delay: u32,
+    /// Smoothing radius of the heatmap, in cells.
+    pub sigma: f32
+}
+
+/// Renders the orders up to and including every window, with older orders fading out. The first window starts at the
+/// first order, and every frame's heatmap is colored on the same scale, so busy and quiet periods can be told apart.
+/// `points` are the grid positions and game frames the heatmap is built from.
+/// `decorate` draws on each finished frame, it gets the first and last game frame the frame covers.
+/// Paths ending in `.gif` get an animated GIF, any other path a directory of numbered PNG files.
+pub fn render(path: &str, background: &DynamicImage, projection: &CellToPixel, points: &[(i32, i32, i32)], markers: &[Marker],
+              settings: &Settings, decorate: impl Fn(&mut DynamicImage, i32, i32)) -> Result<(), Error> {
+    let window = settings.window.max(1);
+    let fade_frames = (settings.fade.max(1) * window) as f32;
+    let frames = || points.iter().map(|&(_, _, frame)| frame).chain(markers.iter().map(|marker| marker.frame));
+    let first_frame = frames().min().unwrap_or(0);
+    let last_frame = frames().max().unwrap_or(0);
+    // the last game frame of every animation frame
+    let ends: Vec<i32> = (0..).map(|i| first_frame + window - 1 + i * window).take_while(|&end| end - window < last_frame).collect();
+    // how much is left of an order of the given frame at the end of a window, if anything
+    let opacity = |frame: i32, end: i32| {
+        let age = (end - frame) as f32;
+        if frame > end || age >= fade_frames { None } else { Some(1.0 - age / fade_frames) }
+    };
+    let heatmap = |end: i32| {
+        let mut heatmap = Heatmap::new(projection.grid_size());
+        for &(x, y, frame) in points {
+            if let Some(weight) = opacity(frame, end) {
+                heatmap.add_weighted(x, y, weight);
+            }
+        }
+        heatmap
+    };
+    let max = ends.iter().map(|&end| heatmap(end).max_density(settings.sigma)).fold(0.0, f32::max);
+
+    // every frame is written as soon as it is rendered, a long game has thousands of them
+    let mut gif = if path.ends_with(".gif") {
+        Some(GifEncoder::new(BufWriter::new(File::create(path)?)))
+    } else {
+        fs::create_dir_all(path)?;
+        None
+    };
+    for (count, &end) in ends.iter().enumerate() {
+        let mut image = background.clone();
+        heatmap(end).render_scaled(&mut image, projection, settings.sigma, max);
+        for marker in markers {
+            if let Some(opacity) = opacity(marker.frame, end) {
+                markers::draw(&mut image, projection, marker, opacity);
+            }
+        }
+        decorate(&mut image, end - window + 1, end);
+        match &mut gif {
+            Some(encoder) => {
+                let frame = Frame::from_parts(image.to_rgba(), 0, 0, Delay::from_numer_denom_ms(settings.delay, 1));
+                encoder.encode_frame(frame).map_err(|e| Error::other(format!("Could not write {}: {}", path, e)))?;
+            },
+            None => {
+                let file = Path::new(path).join(format!("frame_{:04}.png", count));
+                image.save(&file).map_err(|e| Error::other(format!("Could not write {}: {}", file.display(), e)))?;
+            }
+        }
+    }
+
+    println!("Saved animation of {} frames.", ends.len());
+    Ok(())
+}
@@ -0,0 +1,113 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The map information returned by the resource site.
+pub const MAP_JSON: &str = "map.json";
+/// The picture of the map found on the resource site: a screenshot or the minimap preview.
+pub const PICTURE: &str = "picture.png";
+pub const MAP_PACKAGE: &str = "map.oramap";
+
+/// Everything downloaded from the resource site, so every map is only looked up once.
+/// Every map gets its own folder `maps/<map hash>` in the cache directory.
+pub struct MapCache {
+    dir: PathBuf
+}
+
+/// A map in the cache, as shown by `cache list`.
+pub struct CachedMap {
+    pub hash: String,
+    pub title: Option<String>,
+    pub files: Vec<String>,
+    /// Total size of the cached files, in bytes.
+    pub size: u64,
+    pub last_used: SystemTime
+}
+
+impl MapCache {
+    /// Uses the given directory, or `openra-heatmap` in the user's cache directory (`$XDG_CACHE_HOME` or `~/.cache` on Linux).
+    pub fn new(dir: Option<&str>) -> Result<Self, Error> {
+        let dir = match dir {
+            Some(dir) => PathBuf::from(dir),
+            None => dirs::cache_dir()
+                .ok_or_else(|| Error::other("Cannot determine the cache directory, use --cache-dir"))?
+                .join("openra-heatmap")
+        };
+        Ok(MapCache { dir })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The folder of a map. Hashes come from replays and requests, so anything but letters and digits,
+    /// which could point outside of the cache, is refused.
+    fn map_dir(&self, hash: &str) -> Result<PathBuf, Error> {
+        if hash.is_empty() || !hash.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Invalid map hash '{}'", hash)));
+        }
+        Ok(self.dir.join("maps").join(hash))
+    }
+
+    pub fn path(&self, hash: &str, name: &str) -> Result<PathBuf, Error> {
+        Ok(self.map_dir(hash)?.join(name))
+    }
+
+    /// A cached file of a map, or None when it has not been downloaded yet.
+    pub fn read(&self, hash: &str, name: &str) -> Option<Vec<u8>> {
+        fs::read(self.path(hash, name).ok()?).ok()
+    }
+
+    pub fn write(&self, hash: &str, name: &str, contents: &[u8]) -> Result<PathBuf, Error> {
+        fs::create_dir_all(self.map_dir(hash)?)?;
+        let path = self.path(hash, name)?;
+        fs::write(&path, contents)?;
+        Ok(path)
+    }
+
+    /// Records that a map was used, which keeps it from being pruned as unused.
+    pub fn touch(&self, hash: &str) -> Result<(), Error> {
+        File::options().write(true).open(self.path(hash, MAP_JSON)?)?.set_modified(SystemTime::now())
+    }
+
+    pub fn list(&self) -> Result<Vec<CachedMap>, Error> {
+        let maps_dir = self.dir.join("maps");
+        if !maps_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut maps = Vec::new();
+        for entry in fs::read_dir(maps_dir)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let hash = entry.file_name().to_string_lossy().into_owned();
+            let Ok(map_json) = self.path(&hash, MAP_JSON) else {
+                continue; // not put there by us
+            };
+            let mut files = Vec::new();
+            let mut size = 0;
+            for file in fs::read_dir(entry.path())? {
+                let file = file?;
+                size += file.metadata()?.len();
+                files.push(file.file_name().to_string_lossy().into_owned());
+            }
+            files.sort();
+            let last_used = fs::metadata(map_json)
+                .or_else(|_| entry.metadata())?
+                .modified()?;
+            let title = self.read(&hash, MAP_JSON)
+                .and_then(|json| serde_json::from_slice::<serde_json::Value>(&json).ok())
+                .and_then(|json| json[0]["title"].as_str().map(|title| title.to_string()));
+            maps.push(CachedMap { hash, title, files, size, last_used });
+        }
+        maps.sort_by_key(|map| std::cmp::Reverse(map.last_used));
+        Ok(maps)
+    }
+
+    /// Removes everything cached for a map.
+    pub fn remove(&self, hash: &str) -> Result<(), Error> {
+        fs::remove_dir_all(self.map_dir(hash)?)
+    }
+}
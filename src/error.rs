@@ -0,0 +1,138 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while reading a replay. Offsets are byte offsets into the replay file.
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    /// The replay ended while `wanted` more bytes were expected at `offset`.
+    Truncated { offset: usize, wanted: usize },
+    /// A position or length pointing outside the replay, which is `len` bytes long.
+    OutOfRange { offset: usize, len: usize },
+    BadMarker { offset: usize, expected: i32, found: i32 },
+    /// The replay ends without metadata trailer, as replays of games that did not finish do.
+    NoMetadata,
+    UnknownOrderType { offset: usize, byte: u8 },
+    UnknownTargetType { offset: usize, byte: u8 },
+    /// A 7-bit encoded integer longer than 5 bytes.
+    BadVarint { offset: usize },
+    InvalidUtf8 { offset: usize },
+    /// The metadata is readable but lacks something we need.
+    MissingMetadata { field: &'static str },
+    InvalidMetadata { field: &'static str, value: String },
+    /// The metadata is not valid MiniYaml.
+    Metadata(YamlError)
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "{}", e),
+            ReplayError::Truncated { offset, wanted } => write!(f, "replay truncated: expected {} more bytes at offset {}", wanted, offset),
+            ReplayError::OutOfRange { offset, len } => write!(f, "offset {} is outside of the replay ({} bytes)", offset, len),
+            ReplayError::BadMarker { offset, expected, found } => write!(f, "expected marker {} at offset {}, found {}", expected, offset, found),
+            ReplayError::NoMetadata => write!(f, "replay has no metadata, the game did not finish; read it in salvage mode to use what was recorded"),
+            ReplayError::UnknownOrderType { offset, byte } => write!(f, "unknown order type {:#04x} at offset {}", byte, offset),
+            ReplayError::UnknownTargetType { offset, byte } => write!(f, "unknown target type {:#04x} at offset {}", byte, offset),
+            ReplayError::BadVarint { offset } => write!(f, "malformed 7-bit encoded integer at offset {}", offset),
+            ReplayError::InvalidUtf8 { offset } => write!(f, "invalid UTF-8 string at offset {}", offset),
+            ReplayError::MissingMetadata { field } => write!(f, "replay metadata lacks {}", field),
+            ReplayError::InvalidMetadata { field, value } => write!(f, "replay metadata has invalid {} '{}'", field, value),
+            ReplayError::Metadata(e) => write!(f, "replay metadata: {}", e)
+        }
+    }
+}
+
+impl Error for ReplayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ReplayError::Io(e) => Some(e),
+            ReplayError::Metadata(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<YamlError> for ReplayError {
+    fn from(e: YamlError) -> Self {
+        ReplayError::Metadata(e)
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// A MiniYaml document that can't be parsed. Lines are counted from 1.
+#[derive(Debug)]
+pub enum YamlError {
+    /// A node indented more than one level deeper than the node before it.
+    BadIndentation { line: usize }
+}
+
+impl fmt::Display for YamlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            YamlError::BadIndentation { line } => write!(f, "line {} is indented too deep", line)
+        }
+    }
+}
+
+impl Error for YamlError {}
+
+/// Everything that can go wrong while reading a map package.
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Zip(zip::result::ZipError),
+    MissingFile(&'static str),
+    MissingField(&'static str),
+    InvalidField { field: &'static str, value: String },
+    /// map.yaml is not valid MiniYaml.
+    Yaml(YamlError)
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "{}", e),
+            MapError::Zip(e) => write!(f, "{}", e),
+            MapError::MissingFile(name) => write!(f, "map has no {}", name),
+            MapError::MissingField(field) => write!(f, "map.yaml lacks {}", field),
+            MapError::InvalidField { field, value } => write!(f, "map.yaml has invalid {} '{}'", field, value),
+            MapError::Yaml(e) => write!(f, "map.yaml: {}", e)
+        }
+    }
+}
+
+impl Error for MapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MapError::Io(e) => Some(e),
+            MapError::Zip(e) => Some(e),
+            MapError::Yaml(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(e: io::Error) -> Self {
+        MapError::Io(e)
+    }
+}
+
+impl From<YamlError> for MapError {
+    fn from(e: YamlError) -> Self {
+        MapError::Yaml(e)
+    }
+}
+
+impl From<zip::result::ZipError> for MapError {
+    fn from(e: zip::result::ZipError) -> Self {
+        MapError::Zip(e)
+    }
+}
@@ -0,0 +1,119 @@
+use serde_json::{Map, Value};
+use std::io::{Error, Write};
+use std::path::PathBuf;
+use openra_heatmap::{LobbySettings, Order, ReadMode, Replay, Target};
+
+/// How the exported orders are written.
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    /// One JSON object per line.
+    JsonLines,
+    /// Comma separated values, with a header line.
+    Csv
+}
+
+/// The columns of the export, in order.
+const COLUMNS: &[&str] = &[
+    "replay", "frame", "time", "client", "player", "order", "subject", "target_type", "target_actor",
+    "cell_x", "cell_y", "position_x", "position_y", "position_z", "target_string",
+    "extra_location_x", "extra_location_y", "extra_data", "queued",
+    "starting_cash", "tech_level", "short_game", "crates"
+];
+
+/// Writes every order of the replays, one row per order. Replays that can't be read are skipped with a message.
+pub fn export(replays: &[PathBuf], format: Format, mode: ReadMode, out: &mut dyn Write) -> Result<(), Error> {
+    if format == Format::Csv {
+        writeln!(out, "{}", COLUMNS.join(","))?;
+    }
+    for path in replays {
+        let replay = match Replay::open_with_mode(path, mode) {
+            Ok(replay) => replay,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if let Some(warning) = replay.protocol_version().warning() {
+            eprintln!("Warning: {}: {}", path.display(), warning);
+        }
+        let frame_duration = replay.frame_duration();
+        let settings = replay.lobby().map(|lobby| lobby.settings).unwrap_or_default();
+        let name = path.file_name().map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let mut count = 0;
+        for order in replay.orders() {
+            let order = match order {
+                Ok(order) => order,
+                Err(e) => {
+                    eprintln!("Stopped reading {} after {} orders: {}", path.display(), count, e);
+                    break;
+                }
+            };
+            let player = replay.info().players.get(&order.client).map(|player| player.name.clone());
+            let time = order.frame as f64 * frame_duration.as_secs_f64();
+            let row = row(&name, &order, time, player, &settings);
+            match format {
+                Format::JsonLines => {
+                    let object: Map<String, Value> = COLUMNS.iter().map(|column| column.to_string()).zip(row).collect();
+                    writeln!(out, "{}", Value::Object(object))?;
+                },
+                Format::Csv => {
+                    let fields: Vec<String> = row.iter().map(csv_field).collect();
+                    writeln!(out, "{}", fields.join(","))?;
+                }
+            }
+            count += 1;
+        }
+        eprintln!("Exported {} orders of {}", count, path.display());
+    }
+    out.flush()
+}
+
+/// The values of an order, one for each of `COLUMNS`. The lobby settings are the ones of the replay, to group orders by ruleset.
+fn row(replay: &str, order: &Order, time: f64, player: Option<String>, settings: &LobbySettings) -> Vec<Value> {
+    let (target_type, actor, cell, position) = match order.target {
+        Target::Invalid => ("none", None, None, None),
+        Target::Actor(actor) => ("actor", Some(actor), None, None),
+        Target::FrozenActor { frozen_actor_id, .. } => ("frozen_actor", Some(frozen_actor_id), None, None),
+        Target::Cell { cell, .. } => ("cell", None, Some(cell), None),
+        Target::Position(position) => ("position", None, None, Some(position))
+    };
+    vec![
+        replay.into(),
+        order.frame.into(),
+        ((time * 1000.0).round() / 1000.0).into(),
+        order.client.into(),
+        optional(player),
+        order.order.as_str().into(),
+        optional(order.subject),
+        target_type.into(),
+        optional(actor),
+        optional(cell.map(|cell| cell.x)),
+        optional(cell.map(|cell| cell.y)),
+        optional(position.map(|position| position.x)),
+        optional(position.map(|position| position.y)),
+        optional(position.map(|position| position.z)),
+        optional(order.target_string.clone()),
+        optional(order.extra_location.map(|cell| cell.x)),
+        optional(order.extra_location.map(|cell| cell.y)),
+        optional(order.extra_data),
+        order.queued.into(),
+        optional(settings.starting_cash()),
+        optional(settings.tech_level()),
+        optional(settings.short_game()),
+        optional(settings.crates())
+    ]
+}
+
+fn optional<T: Into<Value>>(value: Option<T>) -> Value {
+    value.map_or(Value::Null, Into::into)
+}
+
+/// A value as CSV field: empty for missing values, strings quoted when needed.
+fn csv_field(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(text) if text.contains(&[',', '"', '\n', '\r'][..]) => format!("\"{}\"", text.replace('"', "\"\"")),
+        Value::String(text) => text.clone(),
+        other => other.to_string()
+    }
+}
@@ -0,0 +1,38 @@
+/// Size in cells (width, height) of the buildings that can be placed in the default mods,
+/// not counting the bib some buildings have in front of them.
+const RA: &[(&str, (i32, i32))] = &[
+    ("fact", (3, 3)), ("powr", (2, 2)), ("apwr", (3, 3)), ("proc", (3, 3)), ("silo", (1, 1)),
+    ("tent", (2, 2)), ("barr", (2, 2)), ("kenn", (1, 1)), ("weap", (3, 2)), ("dome", (2, 2)),
+    ("hpad", (2, 2)), ("afld", (3, 2)), ("spen", (3, 3)), ("syrd", (3, 3)), ("fix", (3, 3)),
+    ("atek", (2, 2)), ("stek", (3, 2)), ("pdox", (2, 2)), ("iron", (2, 2)), ("mslo", (2, 1)),
+    ("gap", (1, 1)), ("agun", (1, 1)), ("sam", (2, 1)), ("pbox", (1, 1)), ("hbox", (1, 1)),
+    ("gun", (1, 1)), ("ftur", (1, 1)), ("tsla", (1, 1)),
+    ("facf", (3, 3)), ("weaf", (3, 2)), ("syrf", (3, 3)), ("spef", (3, 3)), ("domf", (2, 2)),
+    ("sbag", (1, 1)), ("fenc", (1, 1)), ("brik", (1, 1)), ("cycl", (1, 1)), ("barb", (1, 1))
+];
+
+const CNC: &[(&str, (i32, i32))] = &[
+    ("fact", (3, 2)), ("nuke", (2, 2)), ("nuk2", (2, 2)), ("proc", (3, 2)), ("silo", (2, 1)),
+    ("pyle", (2, 2)), ("hand", (2, 2)), ("weap", (3, 2)), ("afld", (4, 2)), ("hq", (2, 2)),
+    ("eye", (2, 2)), ("tmpl", (3, 2)), ("fix", (3, 3)), ("hpad", (2, 2)), ("gtwr", (1, 1)),
+    ("gun", (1, 1)), ("sam", (2, 1)), ("obli", (1, 1)), ("atwr", (1, 1)),
+    ("sbag", (1, 1)), ("cycl", (1, 1)), ("brik", (1, 1)), ("barb", (1, 1)), ("wood", (1, 1))
+];
+
+const D2K: &[(&str, (i32, i32))] = &[
+    ("construction_yard", (3, 3)), ("wind_trap", (2, 2)), ("barracks", (2, 2)), ("refinery", (3, 3)),
+    ("silo", (1, 1)), ("light_factory", (3, 3)), ("heavy_factory", (3, 3)), ("outpost", (2, 2)),
+    ("starport", (3, 3)), ("repair_pad", (3, 3)), ("high_tech_factory", (3, 3)), ("research_centre", (2, 2)),
+    ("palace", (3, 3)), ("wall", (1, 1)), ("medium_gun_turret", (1, 1)), ("large_gun_turret", (1, 1))
+];
+
+/// The size of a building of a mod, or None for unknown mods and buildings.
+pub fn footprint(mod_id: &str, building: &str) -> Option<(i32, i32)> {
+    let table = match mod_id {
+        "ra" => RA,
+        "cnc" => CNC,
+        "d2k" => D2K,
+        _ => return None
+    };
+    table.iter().find(|(name, _)| name.eq_ignore_ascii_case(building)).map(|&(_, size)| size)
+}
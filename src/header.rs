@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use crate::lobby::parse_color;
+use crate::miniyaml::{self, MiniYamlNode};
+use crate::order::Order;
+
+/// A client as it introduced itself in its handshake response. Replays only hold what the server sent,
+/// so these are rare; the clients of the lobby (see `Lobby`) are the better source.
+#[derive(Debug, Clone)]
+pub struct HandshakeClient {
+    pub name: String,
+    /// RGBA, when the client told its color.
+    pub color: Option<[u8; 4]>,
+    pub fingerprint: Option<String>
+}
+
+/// What the handshake and lobby packets at the start of a replay tell about the game. Unlike the metadata
+/// trailer, these are written before the game starts, so replays of crashed or still running games have them too.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayHeader {
+    pub mod_id: Option<String>,
+    pub version: Option<String>,
+    pub map_uid: Option<String>,
+    /// By client index.
+    pub clients: HashMap<i32, HandshakeClient>
+}
+
+impl ReplayHeader {
+    /// Collects the header from the orders at the start of a replay, up to the first order issued in game.
+    pub fn read(orders: impl Iterator<Item = Order>) -> ReplayHeader {
+        let mut header = ReplayHeader::default();
+        for order in orders.take_while(|order| order.immediate) {
+            let nodes = match order.target_string.as_deref().map(miniyaml::parse) {
+                Some(Ok(nodes)) => nodes,
+                _ => continue
+            };
+            let node = |key| nodes.iter().find(|node: &&MiniYamlNode| node.key == key);
+            match order.order.as_str() {
+                // sent by the server to every client that connects
+                "HandshakeRequest" => if let Some(handshake) = node("Handshake") {
+                    header.read_handshake(handshake);
+                },
+                // the answer of a client, introducing itself in a `Client` node next to the `Handshake`
+                "HandshakeResponse" => if let Some(handshake) = node("Handshake") {
+                    header.read_handshake(handshake);
+                    if let Some(client) = node("Client") {
+                        header.clients.insert(order.client, HandshakeClient {
+                            name: client.value_of("Name").unwrap_or_default().to_string(),
+                            color: client.value_of("Color").or_else(|| client.value_of("PreferredColor")).and_then(parse_color),
+                            fingerprint: client.value_of("Fingerprint").filter(|fingerprint| !fingerprint.is_empty()).map(str::to_string)
+                        });
+                    }
+                },
+                // the lobby, which names the map
+                "SyncInfo" => if let Some(map) = node("GlobalSettings").and_then(|settings| settings.value_of("Map")) {
+                    header.map_uid = Some(map.to_string());
+                },
+                _ => {}
+            }
+        }
+        header
+    }
+
+    fn read_handshake(&mut self, handshake: &MiniYamlNode) {
+        let text = |key| handshake.value_of(key).filter(|value| !value.is_empty()).map(str::to_string);
+        self.mod_id = text("Mod").or_else(|| self.mod_id.take());
+        self.version = text("Version").or_else(|| self.version.take());
+        self.map_uid = text("Map").or_else(|| self.map_uid.take());
+    }
+}
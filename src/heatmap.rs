@@ -0,0 +1,150 @@
+use image::{DynamicImage, GenericImageView, Rgba};
+use crate::projection::CellToPixel;
+
+/// Per-cell activity counts for a single map, accumulated over any number of replays.
+/// Cells are addressed by their grid position, see `CellToPixel`.
+pub struct Heatmap {
+    width: usize,
+    height: usize,
+    counts: Vec<f32>
+}
+
+impl Heatmap {
+    pub fn new((width, height): (usize, usize)) -> Self {
+        Heatmap {
+            width,
+            height,
+            counts: vec![0.0; width * height]
+        }
+    }
+
+    /// Count one order targeting the grid position (x, y). Positions outside the map are not counted.
+    pub fn add(&mut self, x: i32, y: i32) {
+        self.add_weighted(x, y, 1.0);
+    }
+
+    /// Count an order for less than a whole one, e.g. an older order in an animation frame.
+    pub fn add_weighted(&mut self, x: i32, y: i32, weight: f32) {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return;
+        }
+        self.counts[y as usize * self.width + x as usize] += weight;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum::<f32>().round() as u64
+    }
+
+    /// Gaussian blur of the counts (separable, sigma in cells), so single clicks become blobs
+    /// and nearby orders reinforce each other.
+    fn smoothed(&self, sigma: f32) -> Vec<f32> {
+        let values = self.counts.clone();
+        if sigma <= 0.0 {
+            return values;
+        }
+        let radius = (sigma * 3.0).ceil() as i32;
+        let kernel: Vec<f32> = (-radius..=radius)
+            .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+
+        let mut horizontal = vec![0.0; values.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = 0.0;
+                for (k, weight) in kernel.iter().enumerate() {
+                    let sx = x as i32 + k as i32 - radius;
+                    if sx >= 0 && sx < self.width as i32 {
+                        sum += values[y * self.width + sx as usize] * weight;
+                    }
+                }
+                horizontal[y * self.width + x] = sum;
+            }
+        }
+
+        let mut result = vec![0.0; values.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = 0.0;
+                for (k, weight) in kernel.iter().enumerate() {
+                    let sy = y as i32 + k as i32 - radius;
+                    if sy >= 0 && sy < self.height as i32 {
+                        sum += horizontal[sy as usize * self.width + x] * weight;
+                    }
+                }
+                result[y * self.width + x] = sum;
+            }
+        }
+        result
+    }
+
+    /// The highest smoothed density, which `render` scales the colors to.
+    pub fn max_density(&self, sigma: f32) -> f32 {
+        self.smoothed(sigma).into_iter().fold(0.0, f32::max)
+    }
+
+    /// Blend the smoothed, color-ramped density over `image`.
+    pub fn render(&self, image: &mut DynamicImage, projection: &CellToPixel, sigma: f32) {
+        self.render_scaled(image, projection, sigma, self.max_density(sigma));
+    }
+
+    /// Like `render`, with the hottest color at density `max` rather than at the highest density of this heatmap,
+    /// so several heatmaps, like the frames of an animation, can be compared.
+    pub fn render_scaled(&self, image: &mut DynamicImage, projection: &CellToPixel, sigma: f32, max: f32) {
+        let density = self.smoothed(sigma);
+        if max <= 0.0 {
+            return;
+        }
+
+        let (image_width, image_height) = GenericImageView::dimensions(image);
+        let sample = |x: usize, y: usize| density[y * self.width + x];
+
+        let buffer = image.as_mut_rgba8().expect("image must be rgba8");
+        for py in 0..image_height {
+            for px in 0..image_width {
+                // bilinear interpolation between cell centers
+                let (cx, cy) = projection.pixel_to_grid(px as f32 + 0.5, py as f32 + 0.5);
+                let x0 = (cx.floor().max(0.0) as usize).min(self.width - 1);
+                let y0 = (cy.floor().max(0.0) as usize).min(self.height - 1);
+                let x1 = (x0 + 1).min(self.width - 1);
+                let y1 = (y0 + 1).min(self.height - 1);
+                let fx = (cx - x0 as f32).clamp(0.0, 1.0);
+                let fy = (cy - y0 as f32).clamp(0.0, 1.0);
+                let top = sample(x0, y0) * (1.0 - fx) + sample(x1, y0) * fx;
+                let bottom = sample(x0, y1) * (1.0 - fx) + sample(x1, y1) * fx;
+                let value = (top * (1.0 - fy) + bottom * fy) / max;
+                if value < 0.02 {
+                    continue;
+                }
+
+                let color = color_ramp(value);
+                let alpha = 0.35 + 0.5 * value;
+                let pixel = buffer.get_pixel_mut(px, py);
+                for c in 0..3 {
+                    pixel[c] = (pixel[c] as f32 * (1.0 - alpha) + color[c] as f32 * alpha) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Maps a normalized density in [0, 1] onto blue -> cyan -> green -> yellow -> red.
+fn color_ramp(value: f32) -> Rgba<u8> {
+    const STOPS: [(f32, [f32; 3]); 5] = [
+        (0.0, [0.0, 0.0, 255.0]),
+        (0.25, [0.0, 255.0, 255.0]),
+        (0.5, [0.0, 255.0, 0.0]),
+        (0.75, [255.0, 255.0, 0.0]),
+        (1.0, [255.0, 0.0, 0.0])
+    ];
+    let value = value.clamp(0.0, 1.0);
+    for pair in STOPS.windows(2) {
+        let (start, from) = pair[0];
+        let (end, to) = pair[1];
+        if value <= end {
+            let t = (value - start) / (end - start);
+            let mix = |i: usize| (from[i] + (to[i] - from[i]) * t) as u8;
+            return Rgba([mix(0), mix(1), mix(2), 255]);
+        }
+    }
+    Rgba([255, 0, 0, 255])
+}
@@ -0,0 +1,38 @@
+//! Reads OpenRA replay (`.orarep`) files: the game information stored in the metadata trailer
+//! and the orders every client issued during the game. Also reads the map packages (`.oramap`) they were played on.
+//!
+//! ```no_run
+//! let replay = openra_heatmap::Replay::open("game.orarep").unwrap();
+//! for order in replay.orders() {
+//!     let order = order.unwrap();
+//!     if let Some(cell) = order.target_cell() {
+//!         println!("{} at frame {}: {} -> {},{}", order.client, order.frame, order.order, cell.x, cell.y);
+//!     }
+//! }
+//! ```
+
+pub mod error;
+pub mod header;
+pub mod lobby;
+pub mod map;
+pub mod metadata;
+pub mod miniyaml;
+pub mod order;
+mod packet;
+pub mod protocol;
+pub mod reader;
+pub mod replay;
+pub mod sync;
+pub mod tracker;
+
+pub use error::{MapError, ReplayError, YamlError};
+pub use header::{HandshakeClient, ReplayHeader};
+pub use lobby::{Lobby, LobbyClient, LobbySettings, LobbySlot};
+pub use map::{Bounds, MapGridType, MapPackage, TileLayers};
+pub use metadata::{Outcome, ReplayMetadata};
+pub use miniyaml::MiniYamlNode;
+pub use order::{CPos, Order, Target, WPos};
+pub use protocol::{OrderEncoding, ProtocolVersion};
+pub use replay::{GameInformation, Orders, Player, ReadMode, ReadStats, Replay};
+pub use sync::{SyncHash, SyncHashes};
+pub use tracker::{ActorTracker, Confidence, TrackedPosition};
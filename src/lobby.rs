@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use crate::miniyaml::{self, MiniYamlNode};
+use crate::order::Order;
+
+/// Milliseconds per tick of the game speeds of the default mods, by the name the lobby uses.
+const GAME_SPEEDS: &[(&str, u64)] = &[
+    ("slowest", 80), ("slower", 50), ("default", 40), ("normal", 40), ("fast", 35), ("faster", 30), ("fastest", 20)
+];
+
+/// Lobby options that older releases wrote as fields of the global settings, with the id newer ones use.
+const LEGACY_OPTIONS: &[(&str, &str)] = &[
+    ("StartingCash", "startingcash"), ("TechLevel", "techlevel"), ("ShortGame", "shortgame"), ("Crates", "crates"),
+    ("Fog", "fog"), ("Shroud", "explored"), ("AllyBuildRadius", "allybuild"), ("StartingUnitsClass", "startingunits")
+];
+
+/// A client in the lobby: a player, a bot or a spectator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LobbyClient {
+    pub index: i32,
+    pub name: String,
+    /// RGBA
+    pub color: Option<[u8; 4]>,
+    /// The internal name of the chosen faction, `Random` before the game resolved it.
+    pub faction: String,
+    /// 0 when the spawn point is picked at random.
+    pub spawn_point: i32,
+    /// 0 when not in a team.
+    pub team: i32,
+    /// The slot the client took, None for spectators.
+    pub slot: Option<String>,
+    /// The kind of AI, for bots.
+    pub bot: Option<String>,
+    pub is_admin: bool
+}
+
+impl LobbyClient {
+    pub fn is_spectator(&self) -> bool {
+        self.slot.is_none()
+    }
+}
+
+/// A player slot of the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LobbySlot {
+    /// The map's player this slot is for, e.g. `Multi0`.
+    pub player_reference: String,
+    pub closed: bool,
+    pub allow_bots: bool
+}
+
+/// The lobby's global settings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LobbySettings {
+    pub server_name: Option<String>,
+    pub map_uid: Option<String>,
+    /// Milliseconds per tick, written out by older releases.
+    pub timestep: Option<u64>,
+    /// The game speed, by name, e.g. `default` or `faster`.
+    pub game_speed: Option<String>,
+    pub random_seed: Option<i32>,
+    /// Identifies the game, so the replays of its clients can be matched.
+    pub game_uid: Option<String>,
+    /// The value of every lobby option by its id, e.g. `startingcash` or `crates`, as the mod defines them.
+    pub options: BTreeMap<String, String>
+}
+
+impl LobbySettings {
+    fn read(node: &MiniYamlNode) -> LobbySettings {
+        let text = |key| node.value_of(key).filter(|value| !value.is_empty()).map(str::to_string);
+        let mut options: BTreeMap<String, String> = node.child("Options").into_iter()
+            .flat_map(|options| &options.children)
+            .filter_map(|option| option.value_of("Value").map(|value| (option.key.clone(), value.to_string())))
+            .collect();
+        for (field, id) in LEGACY_OPTIONS {
+            if let Some(value) = text(field) {
+                options.entry(id.to_string()).or_insert(value);
+            }
+        }
+        LobbySettings {
+            server_name: text("ServerName"),
+            map_uid: text("Map"),
+            timestep: text("Timestep").and_then(|timestep| timestep.parse().ok()),
+            game_speed: text("GameSpeedType").or_else(|| options.get("gamespeed").cloned()),
+            random_seed: text("RandomSeed").and_then(|seed| seed.parse().ok()),
+            game_uid: text("GameUid"),
+            options
+        }
+    }
+
+    /// Milliseconds per tick: the timestep when it was written out, else the one of the named game speed.
+    pub fn tick_duration(&self) -> Option<u64> {
+        self.timestep.or_else(|| {
+            let speed = self.game_speed.as_deref()?;
+            GAME_SPEEDS.iter().find(|(name, _)| *name == speed).map(|&(_, timestep)| timestep)
+        })
+    }
+
+    pub fn option(&self, id: &str) -> Option<&str> {
+        self.options.get(id).map(String::as_str)
+    }
+
+    pub fn starting_cash(&self) -> Option<i32> {
+        self.option("startingcash")?.parse().ok()
+    }
+
+    /// E.g. `unrestricted`, `low` or `infonly`.
+    pub fn tech_level(&self) -> Option<&str> {
+        self.option("techlevel")
+    }
+
+    /// Whether players are defeated once they lost all buildings, rather than all units too.
+    pub fn short_game(&self) -> Option<bool> {
+        self.option("shortgame").and_then(parse_bool)
+    }
+
+    pub fn crates(&self) -> Option<bool> {
+        self.option("crates").and_then(parse_bool)
+    }
+}
+
+/// The state of the lobby as of an order that synced it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Lobby {
+    /// The frame of the order the snapshot was taken at, 0 in the lobby before the game.
+    pub frame: i32,
+    /// Sorted by index.
+    pub clients: Vec<LobbyClient>,
+    pub slots: Vec<LobbySlot>,
+    pub settings: LobbySettings
+}
+
+impl Lobby {
+    /// Updates the lobby from a `SyncInfo` order, which syncs all of it, or from one of the `SyncLobbyClients`,
+    /// `SyncLobbySlots` and `SyncLobbyGlobalSettings` orders newer releases sync parts of it with.
+    /// Returns false for other orders and for lobby orders that can't be read.
+    pub fn apply(&mut self, order: &Order) -> bool {
+        if !order.immediate {
+            return false;
+        }
+        let (clients, slots, settings) = match order.order.as_str() {
+            "SyncInfo" => (true, true, true),
+            "SyncLobbyClients" => (true, false, false),
+            "SyncLobbySlots" => (false, true, false),
+            "SyncLobbyGlobalSettings" => (false, false, true),
+            _ => return false
+        };
+        let nodes = match order.target_string.as_deref().map(miniyaml::parse) {
+            Some(Ok(nodes)) => nodes,
+            _ => return false
+        };
+        if clients {
+            self.clients = nodes.iter().filter(|node| node.name() == "Client").filter_map(read_client).collect();
+            self.clients.sort_by_key(|client| client.index);
+        }
+        if slots {
+            self.slots = nodes.iter().filter(|node| node.name() == "Slot").map(read_slot).collect();
+        }
+        if settings {
+            if let Some(node) = nodes.iter().find(|node| node.key == "GlobalSettings") {
+                self.settings = LobbySettings::read(node);
+            }
+        }
+        self.frame = order.frame;
+        true
+    }
+
+    /// The lobby after every order that changed it.
+    pub fn snapshots(orders: impl Iterator<Item = Order>) -> Vec<Lobby> {
+        let mut lobby = Lobby::default();
+        let mut snapshots = Vec::new();
+        for order in orders {
+            if lobby.apply(&order) {
+                snapshots.push(lobby.clone());
+            }
+        }
+        snapshots
+    }
+
+    /// The clients that took a slot, in the order of the slots, which is the order the game creates their players in.
+    pub fn playing_clients(&self) -> Vec<i32> {
+        self.slots.iter()
+            .filter_map(|slot| self.clients.iter().find(|client| client.slot.as_deref() == Some(slot.player_reference.as_str())))
+            .map(|client| client.index)
+            .collect()
+    }
+
+    pub fn client(&self, index: i32) -> Option<&LobbyClient> {
+        self.clients.iter().find(|client| client.index == index)
+    }
+}
+
+fn read_client(node: &MiniYamlNode) -> Option<LobbyClient> {
+    let text = |key| node.value_of(key).filter(|value| !value.is_empty()).map(str::to_string);
+    let number = |key| node.value_of(key).and_then(|value| value.parse().ok()).unwrap_or(0);
+    Some(LobbyClient {
+        index: node.value_of("Index")?.parse().ok()?,
+        name: text("Name").unwrap_or_default(),
+        color: node.value_of("Color").and_then(parse_color),
+        faction: text("Faction").unwrap_or_default(),
+        spawn_point: number("SpawnPoint"),
+        team: number("Team"),
+        slot: text("Slot"),
+        bot: text("Bot"),
+        is_admin: node.value_of("IsAdmin").and_then(parse_bool).unwrap_or(false)
+    })
+}
+
+fn read_slot(node: &MiniYamlNode) -> LobbySlot {
+    let flag = |key| node.value_of(key).and_then(parse_bool).unwrap_or(false);
+    LobbySlot {
+        player_reference: node.value_of("PlayerReference").unwrap_or_else(|| node.key.split_once('@').map_or("", |(_, id)| id)).to_string(),
+        closed: flag("Closed"),
+        allow_bots: flag("AllowBots")
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None
+    }
+}
+
+/// A color as `RRGGBB`, or `RRGGBBAA` when it is not opaque, the way OpenRA writes them.
+pub(crate) fn parse_color(value: &str) -> Option<[u8; 4]> {
+    let rgb = u32::from_str_radix(value.get(..6)?, 16).ok()?;
+    let alpha = match value.get(6..)? {
+        "" => 255,
+        alpha if alpha.len() == 2 => u8::from_str_radix(alpha, 16).ok()?,
+        _ => return None
+    };
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, alpha])
+}
@@ -1,550 +1,829 @@
-use clap::Clap;
-use memmap::Mmap;
-use std::fs::File;
-use std::io::{Write, Error, ErrorKind};
-use byteorder::{ByteOrder, LittleEndian};
-use encoding::all::UTF_8;
-use encoding::{Encoding, DecoderTrap};
-use num_enum::TryFromPrimitive;
-use std::convert::TryFrom;
-use serde_json::{Value};
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
-use std::collections::HashMap;
+use clap::{AppSettings, Clap};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Error};
 use image::GenericImageView;
 use image::DynamicImage;
 use image::{Rgba};
 use rusttype::Font;
 use rusttype::Scale;
-use std::path::Path;
-use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::net::SocketAddr;
+use std::time::Duration;
 
 #[macro_use] extern crate lazy_static;
-use bytes::Buf;
+
+mod animation;
+mod cache;
+mod export;
+mod footprint;
+mod heatmap;
+mod markers;
+mod minimap;
+mod projection;
+mod resource;
+mod server;
+mod style;
+use cache::MapCache;
+use footprint::footprint;
+use heatmap::Heatmap;
+use markers::{Marker, MarkerKind, Shape, GRAY, WHITE};
+use minimap::Palette;
+use projection::CellToPixel;
+use resource::{parse_map_info, ResourceClient};
+use style::StyleSheet;
+use std::collections::{BTreeMap, BTreeSet};
+use openra_heatmap::{sync, ActorTracker, Bounds, Confidence, LobbySettings, MapGridType, MapPackage, Outcome, Player, ReadMode, Replay, SyncHash, Target};
 
 #[derive(Clap)]
 #[clap(version = "0.1", author = "Paul P.")]
+#[clap(setting = AppSettings::SubcommandsNegateReqs)]
 struct Opts {
-    replay_filename: String
+    #[clap(subcommand)]
+    command: Option<Command>,
+    /// Replay files (.orarep) or directories containing them, all played on the same map
+    #[clap(required = true)]
+    replays: Vec<String>,
+    /// Where to write the resulting image
+    #[clap(short, long, default_value = "output.png")]
+    output: String,
+    /// Smoothing radius of the heatmap, in cells
+    #[clap(long, default_value = "2.0")]
+    sigma: f32,
+    /// Also draw a marker for every individual order
+    #[clap(long)]
+    markers: bool,
+    /// Style file telling which orders to include and how to draw them, see default-style.toml
+    #[clap(long)]
+    style: Option<String>,
+    /// Also write an animation of the orders over time: an animated GIF if the path ends in .gif,
+    /// otherwise a directory of numbered PNG frames
+    #[clap(long)]
+    animate: Option<String>,
+    /// Game frames covered by each frame of the animation
+    #[clap(long, default_value = "250")]
+    window: i32,
+    /// Number of animation frames over which an order fades out
+    #[clap(long, default_value = "3")]
+    fade: i32,
+    /// How long each frame of the animation is shown, in milliseconds
+    #[clap(long, default_value = "200")]
+    frame_delay: u32,
+    /// Only plot orders from this point of the game on: a game time (mm:ss) or a frame number
+    #[clap(long, parse(try_from_str = parse_game_time))]
+    from: Option<GameTime>,
+    /// Only plot orders up to this point of the game: a game time (mm:ss) or a frame number
+    #[clap(long, parse(try_from_str = parse_game_time))]
+    to: Option<GameTime>,
+    /// Only plot the orders of this player, by name or client index. Can be repeated
+    #[clap(long, number_of_values = 1)]
+    player: Vec<String>,
+    /// Only plot the orders of the players in this team. Can be repeated
+    #[clap(long, number_of_values = 1)]
+    team: Vec<i32>,
+    /// Also write an image per player, named after the output file and the player
+    #[clap(long)]
+    per_player: bool,
+    /// Which orders targeting an actor to plot at its approximate position: none, those targeting buildings
+    /// that could be matched to where they were placed, or all, including buildings matched by a guess
+    /// and units at the last cell they were ordered to
+    #[clap(long, default_value = "none", possible_values = &["none", "buildings", "all"])]
+    actor_targets: String,
+    /// Use what can be read from truncated replays (crashed or still running games)
+    #[clap(long)]
+    salvage: bool,
+    /// Local .oramap file or unpacked map folder to use instead of looking the map up online
+    #[clap(long)]
+    map: Option<String>,
+    /// Render the map from its tiles, even when a screenshot or preview is available
+    #[clap(long)]
+    render_map: bool,
+    /// Tileset definition of the mod (e.g. mods/ra/tilesets/temperat.yaml) with the terrain colors for rendering the map
+    #[clap(long)]
+    tileset: Option<String>,
+    /// Size of a cell in pixels when rendering the map
+    #[clap(long, default_value = "8")]
+    cell_size: u32,
+    /// The local map uses an isometric grid (Tiberian Sun)
+    #[clap(long)]
+    isometric: bool,
+    /// Where to keep the map information and screenshots downloaded from the resource site
+    #[clap(long, global = true)]
+    cache_dir: Option<String>,
+    /// Only use maps that are already in the cache, never go online
+    #[clap(long)]
+    offline: bool,
+    /// The OpenRA resource site to look up maps on, or a mirror of it
+    #[clap(long, default_value = resource::DEFAULT_URL)]
+    resource_url: String,
+    /// Give up on a request to the resource site after this many seconds
+    #[clap(long, default_value = "30")]
+    timeout: u64,
+    /// How many times to retry a failed request to the resource site
+    #[clap(long, default_value = "2")]
+    retries: u32
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Inspect or clean up the maps downloaded from the resource site
+    Cache(CacheOpts),
+    /// Serve the cached maps like the resource site does, for use with --resource-url
+    Serve(ServeOpts),
+    /// Write every order of the replays as JSON Lines or CSV
+    Export(ExportOpts),
+    /// Show the game, map and players of replays
+    Info(InfoOpts)
+}
+
+#[derive(Clap)]
+struct CacheOpts {
+    #[clap(subcommand)]
+    command: CacheCommand
+}
+
+#[derive(Clap)]
+enum CacheCommand {
+    /// List the cached maps, most recently used first
+    List,
+    /// Remove cached maps
+    Prune(PruneOpts)
+}
+
+#[derive(Clap)]
+struct ServeOpts {
+    /// Address to listen on
+    #[clap(long, default_value = "127.0.0.1:8080")]
+    listen: SocketAddr
+}
+
+#[derive(Clap)]
+struct ExportOpts {
+    /// Replay files (.orarep) or directories containing them
+    #[clap(required = true)]
+    replays: Vec<String>,
+    /// jsonl for one JSON object per order, csv for a table with a header line
+    #[clap(long, default_value = "jsonl", possible_values = &["jsonl", "csv"])]
+    format: String,
+    /// Where to write the orders, instead of standard output
+    #[clap(short, long)]
+    output: Option<String>,
+    /// Use what can be read from truncated replays (crashed or still running games)
+    #[clap(long)]
+    salvage: bool
+}
+
+#[derive(Clap)]
+struct InfoOpts {
+    /// Replay files (.orarep) or directories containing them
+    #[clap(required = true)]
+    replays: Vec<String>,
+    /// Also read truncated replays (crashed or still running games)
+    #[clap(long)]
+    salvage: bool
 }
 
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
-#[repr(u8)]
-enum TargetType { Invalid, Actor, Terrain, FrozenActor }
-
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
-#[repr(u8)]
-enum OrderType {
-    SyncHash = 0x65,
-	Disconnect = 0xBF,
-	Handshake = 0xFE,
-	Fields = 0xFF
+#[derive(Clap)]
+struct PruneOpts {
+    /// Hashes of the maps to remove
+    hashes: Vec<String>,
+    /// Remove the maps that were not used for this many days
+    #[clap(long)]
+    unused_for: Option<u64>,
+    /// Remove all cached maps
+    #[clap(long)]
+    all: bool
 }
-#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
-#[repr(i16)]
-enum OrderFields{
-		None = 0x0,
-		Target = 0x01,
-		ExtraActors = 0x02,
-		TargetString = 0x04,
-		Queued = 0x08,
-		ExtraLocation = 0x10,
-		ExtraData = 0x20,
-		TargetIsCell = 0x40,
-		Subject = 0x80,
-		Grouped = 0x100
+
+/// A point in a game, see `--from` and `--to`.
+#[derive(Clone, Copy)]
+enum GameTime {
+    Frame(i32),
+    Time(Duration)
 }
 
-// Given a stream of bytes, extract first number
-fn decode_slice(bytes: &[u8], index: &mut usize) -> Result<u32, Error> {
-    // Read out an Int32 7 bits at a time.  The high bit
-    // of the byte when on means to continue reading more bytes.
-    let mut count : u32 = 0;
-    let mut shift : u32 = 0;
-    let mut b:u16;
-    loop {
-        // ReadByte handles end of stream cases for us.
-        b = bytes[*index] as u16;
-        let tmp = b & 0x7F;
-        let tmp2 = (tmp as u32) << shift;
-        count |= tmp2 as u32;
-        shift += 7;
-        *index += 1;
-
-        if (b & 0x80) == 0 {
-            return Ok(count)
+impl GameTime {
+    /// The frame of a replay this point falls on.
+    fn to_frame(self, frame_duration: Duration) -> i32 {
+        match self {
+            GameTime::Frame(frame) => frame,
+            GameTime::Time(time) => (time.as_millis() / frame_duration.as_millis().max(1)) as i32
         }
     }
 }
 
-fn construct_headers() -> HeaderMap {
-    let mut headers = HeaderMap::new();
-    headers.insert(ACCEPT, HeaderValue::from_str("*/*").unwrap());  
-    headers
+/// Reads a game time as `mm:ss` (or `hh:mm:ss`), or a frame number.
+fn parse_game_time(value: &str) -> Result<GameTime, String> {
+    let invalid = || format!("{} is neither a game time (mm:ss) nor a frame number", value);
+    if !value.contains(':') {
+        return value.parse().map(GameTime::Frame).map_err(|_| invalid());
+    }
+    let mut seconds = 0;
+    for part in value.split(':') {
+        let part: u64 = part.parse().map_err(|_| invalid())?;
+        seconds = seconds * 60 + part;
+    }
+    Ok(GameTime::Time(Duration::from_secs(seconds)))
 }
 
-//if https://github.com/OpenRA/OpenRA-Resources/pull/365 get submitted we don't have to do this anymore
-fn find_screenshot_id(map_id : u32) -> Option<u32> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"/screenshots/(\d+)/").unwrap();
-    }
-    let client = reqwest::blocking::Client::new();
-    let url = format!("{}/{}", "https://resource.openra.net/maps", map_id);
-    let response = client
-                        .get(&url)
-                        .headers(construct_headers())
-                        .send()
-                        .unwrap();
-    let buffer = response.text().expect("cannot read response");
-    
-    if RE.is_match(&buffer) {
-        let caps = RE.captures(&buffer).unwrap();
-        return Some(caps.get(1).map_or(0, |m| m.as_str().parse::<u32>().expect("Cannot parse screenshot id")));
-    } else {
-        return None;
+/// Whether the orders of a player are plotted, according to --player and --team.
+fn is_selected(player: &Player, opts: &Opts) -> bool {
+    if opts.player.is_empty() && opts.team.is_empty() {
+        return true;
     }
+    opts.player.iter().any(|selected| selected.eq_ignore_ascii_case(&player.name) || selected.parse() == Ok(player.client_id))
+        || opts.team.contains(&player.team)
 }
 
-struct MapInfo {
-    id: u32,
-    width: u16,
-    height: u16
+/// The image of a single player for --per-player: `output.png` becomes `output_<player>.png`.
+fn player_output(output: &str, name: &str) -> PathBuf {
+    let path = Path::new(output);
+    let name: String = name.chars().map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    let stem = path.file_stem().map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}_{}.{}", stem, name, extension.to_string_lossy())),
+        None => path.with_file_name(format!("{}_{}", stem, name))
+    }
 }
 
-fn get_map_info(hash: &str) -> Result<MapInfo, Error> {
-
-    let client = reqwest::blocking::Client::new();
-    let url = format!("{}/{}", "https://resource.openra.net/map/hash/", hash);
-    let response = client                        
-                        .get(&url)
-                        .headers(construct_headers())
-                        .send()
-                        .unwrap();
-    // println!("Response: {:?}", response);
-    let map_info: Value = response.json().unwrap();
-    // println!("map_info: {:?}", map_info);
-    let object = &map_info[0];
-    let id = object["id"].as_u64().unwrap() as u32;
-    // println!("object: {:?}", object);
-    let height = object["height"].as_str().unwrap().parse::<u16>().expect("cannot parse height");
-    // println!("height: {:?}", height);
-    let width = object["width"].as_str().unwrap().parse::<u16>().expect("cannot parse width");
-    // println!("width: {:?}", width);
-
-    Ok(MapInfo {
-        id,
-        width,
-        height
-    })   
+/// The orders to draw on one image.
+#[derive(Default)]
+struct Plot {
+    /// Grid position and frame of every located order.
+    points: Vec<(i32, i32, i32)>,
+    markers: Vec<Marker>,
+    /// The players whose orders these are, once per replay.
+    players: Vec<Player>,
+    replays: usize,
+    /// The lobby settings the replays were played with, see `ruleset`.
+    rulesets: BTreeSet<String>
 }
 
-fn read_screenshot(path: &str) -> DynamicImage {
-    // Use the open function to load an image from a Path.
-    // `open` returns a `DynamicImage` on success.
-    let img = image::open(path).expect("Could not open screenshot file");
+/// A file of a map from the cache. When it is not cached yet, it is downloaded and kept in the cache, unless we are offline.
+fn cached(cache: &MapCache, hash: &str, name: &str, offline: bool, download: impl FnOnce() -> Result<Vec<u8>, Error>) -> Result<Vec<u8>, Error> {
+    let path = cache.path(hash, name)?;
+    if let Ok(contents) = fs::read(&path) {
+        return Ok(contents);
+    }
+    if offline {
+        return Err(Error::other(format!("{} is not in the cache, run once without --offline", path.display())));
+    }
+    let contents = download()?;
+    cache.write(hash, name, &contents)?;
+    Ok(contents)
+}
 
-    // The dimensions method returns the images width and height.
-    // println!("dimensions {:?}",GenericImageView::dimensions(&img));
+/// The map the heatmap is drawn on: a picture of the area within its bounds.
+struct MapBackground {
+    bounds: Bounds,
+    grid: MapGridType,
+    image: DynamicImage
+}
 
-    // The color method returns the image's `ColorType`.
-    // println!("{:?}", img.color());
+/// Looks up the map on the OpenRA resource site and downloads a picture of it.
+/// Without picture, the map itself is downloaded and rendered.
+fn download_background(map_uid: &str, resources: &ResourceClient, cache: &MapCache, opts: &Opts) -> Result<MapBackground, Error> {
+    let map_info = cached(cache, map_uid, cache::MAP_JSON, opts.offline, || resources.map_info(map_uid))
+        .and_then(|json| parse_map_info(&json))
+        .map_err(|e| Error::other(format!("Could not get map info: {}", e)))?;
+    cache.touch(map_uid)?;
+    let picture = if opts.render_map {
+        None
+    } else {
+        match cached(cache, map_uid, cache::PICTURE, opts.offline, || resources.map_picture(&map_info)) {
+            Ok(picture) => Some(picture),
+            Err(e) => {
+                println!("{}", e);
+                None
+            }
+        }
+    };
+    let picture = match picture {
+        Some(picture) => picture,
+        None => {
+            println!("No picture used - rendering the map itself");
+            cached(cache, map_uid, cache::MAP_PACKAGE, opts.offline, || resources.map_package(map_info.id))?;
+            let oramap = cache.path(map_uid, cache::MAP_PACKAGE)?;
+            let map = MapPackage::open(&oramap).map_err(|e| Error::other(format!("Could not read map {}: {}", oramap.display(), e)))?;
+            return rendered_background(&map, map_info.grid, opts);
+        }
+    };
+    let image = image::load_from_memory(&picture).map_err(|e| Error::other(format!("Could not read the picture of map {}: {}", map_info.id, e)))?;
+    Ok(MapBackground {
+        bounds: map_info.bounds,
+        grid: map_info.grid,
+        image: DynamicImage::ImageRgba8(image.to_rgba())
+    })
+}
 
-    img
+/// Uses the preview embedded in a local .oramap file or unpacked map folder, or renders the map if it has none.
+fn local_background(path: &str, opts: &Opts) -> Result<MapBackground, Error> {
+    println!("Reading map from : {}", path);
+    let map = MapPackage::open(path).map_err(|e| Error::other(format!("Could not read map {}: {}", path, e)))?;
+    let grid = if opts.isometric { MapGridType::RectangularIsometric } else { MapGridType::Rectangular };
+    let preview = match map.preview() {
+        Some(preview) if !opts.render_map => preview,
+        _ => return rendered_background(&map, grid, opts)
+    };
+    let image = image::load_from_memory(preview).map_err(|e| Error::other(format!("Could not read map.png of {}: {}", path, e)))?;
+    Ok(MapBackground {
+        bounds: map.bounds,
+        grid,
+        image: DynamicImage::ImageRgba8(image.to_rgba())
+    })
 }
 
-struct ReplayReader {
-    pos: usize,
-    map: Mmap
+/// Paints a minimap from the tiles and resources in map.bin.
+fn rendered_background(map: &MapPackage, grid: MapGridType, opts: &Opts) -> Result<MapBackground, Error> {
+    let palette = match &opts.tileset {
+        Some(path) => Palette::from_tileset_yaml(path)?,
+        None => {
+            // template ids differ between tilesets, so the built-in palette can only guess which tiles are water
+            println!("Warning: no --tileset given, the terrain of {} ({}) is drawn in approximate colors", map.title, map.tileset);
+            Palette::builtin(&map.tileset)
+        }
+    };
+    let layers = map.tiles().map_err(|e| Error::other(format!("Could not read the tiles of {}: {}", map.title, e)))?;
+    Ok(MapBackground {
+        bounds: map.bounds,
+        grid,
+        image: minimap::render(&layers, &palette, map.bounds, grid, opts.cell_size)
+    })
 }
 
-impl ReplayReader {
-    pub fn new(map: Mmap) -> Self {
-        ReplayReader {
-            pos: 0,
-            map: map
+/// Expands every directory argument into the `.orarep` files it contains.
+fn collect_replays(paths: &[String]) -> Result<Vec<PathBuf>, Error> {
+    let mut replays = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut found: Vec<PathBuf> = fs::read_dir(path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "orarep"))
+                .collect();
+            found.sort();
+            replays.append(&mut found);
+        } else {
+            replays.push(path.to_path_buf());
         }
     }
+    Ok(replays)
+}
 
-    pub fn len(&self) -> usize {
-        self.map.len()
+/// Runs `cache list` and `cache prune`.
+fn cache_command(command: &CacheCommand, cache: &MapCache) -> Result<(), Error> {
+    let maps = cache.list()?;
+    match command {
+        CacheCommand::List => {
+            println!("{} maps cached in {}", maps.len(), cache.dir().display());
+            for map in &maps {
+                let days = map.last_used.elapsed().map_or(0, |age| age.as_secs() / (24 * 3600));
+                println!("{}  {:<30}  {:>8} KiB  used {} days ago  ({})",
+                         map.hash, map.title.as_deref().unwrap_or("?"), map.size.div_ceil(1024), days, map.files.join(", "));
+            }
+        },
+        CacheCommand::Prune(prune) => {
+            let selected: Vec<_> = if !prune.hashes.is_empty() {
+                for hash in prune.hashes.iter().filter(|hash| !maps.iter().any(|map| &map.hash == *hash)) {
+                    println!("Map {} is not cached", hash);
+                }
+                maps.iter().filter(|map| prune.hashes.contains(&map.hash)).collect()
+            } else if prune.all {
+                maps.iter().collect()
+            } else if let Some(days) = prune.unused_for {
+                let max_age = Duration::from_secs(days * 24 * 3600);
+                maps.iter().filter(|map| map.last_used.elapsed().is_ok_and(|age| age > max_age)).collect()
+            } else {
+                return Err(Error::other("Tell which maps to prune: their hashes, --unused-for <days> or --all"));
+            };
+            for map in &selected {
+                cache.remove(&map.hash)?;
+                println!("Removed {} {}", map.hash, map.title.as_deref().unwrap_or(""));
+            }
+            println!("Pruned {} of {} cached maps", selected.len(), maps.len());
+        }
     }
+    Ok(())
+}
 
-    pub fn read_string(&mut self) -> String {
-        let strlength = decode_slice(&self.map, &mut self.pos).unwrap() as usize;
-
-        let rpos = self.pos + strlength;
-        let string = UTF_8.decode(&self.map[self.pos..rpos], DecoderTrap::Replace).unwrap();
-        self.pos = rpos;
-        string
+/// Names the players (or counts the replays) and, with --markers, explains the marker colors.
+fn draw_legend(image: &mut DynamicImage, font: &Font, plot: &Plot, styles: &StyleSheet, opts: &Opts) {
+    let (players, replay_count) = (&plot.players, plot.replays);
+    if replay_count == 1 {
+        for (i, player) in players.iter().enumerate() {
+            imageproc::drawing::draw_text_mut(image, Rgba(player.color), 10, 10 + i as u32 * 50, Scale {x: 40.0, y: 40.0},  font, &player.name);
+        }
+    } else if let Some(player) = players.first().filter(|first| players.iter().all(|player| player.name == first.name)) {
+        let text = format!("{} in {} replays", player.name, replay_count);
+        imageproc::drawing::draw_text_mut(image, Rgba(player.color), 10, 10, Scale {x: 40.0, y: 40.0},  font, &text);
+    } else {
+        imageproc::drawing::draw_text_mut(image, WHITE, 10, 10, Scale {x: 40.0, y: 40.0},  font, &format!("{} replays", replay_count));
     }
-
-    pub fn read_string_with_length(&mut self, strlength: usize) -> String {
-
-        let rpos = self.pos + strlength;
-        let string = UTF_8.decode(&self.map[self.pos..rpos], DecoderTrap::Strict).unwrap();
-        self.pos = rpos;
-        string
+    if opts.markers {
+        let listed = styles.styles.iter()
+            .filter(|style| style.include && style.shape != Shape::None)
+            .filter(|style| opts.actor_targets != "none" || !style.targets_actors());
+        let labels = listed.filter_map(|style| style.label.as_ref().map(|label| (label, style.color)));
+        for (i, (label, color)) in labels.enumerate() {
+            imageproc::drawing::draw_text_mut(image, color, 500, 10 + i as u32 * 50, Scale {x: 40.0, y: 40.0},  font, label);
+        }
     }
+    let ruleset = match plot.rulesets.len() {
+        0 => return,
+        1 => plot.rulesets.iter().next().cloned().unwrap_or_default(),
+        count => format!("{} different rulesets", count)
+    };
+    let (_, height) = GenericImageView::dimensions(image);
+    imageproc::drawing::draw_text_mut(image, WHITE, 10, height.saturating_sub(100), Scale {x: 40.0, y: 40.0},  font, &ruleset);
+}
 
-    pub fn read_i32(&mut self) -> i32 {
-        let integer = LittleEndian::read_i32(&self.map[self.pos..self.pos+4]);
-        self.pos += 4;
-        integer
+/// The lobby settings that change how a game plays, e.g. `$10000, tech unrestricted, short game, no crates`.
+fn ruleset(settings: &LobbySettings) -> String {
+    let mut parts = Vec::new();
+    if let Some(cash) = settings.starting_cash() {
+        parts.push(format!("${}", cash));
     }
-
-    pub fn read_u32(&mut self) -> u32 {
-        let integer = LittleEndian::read_u32(&self.map[self.pos..self.pos+4]);
-        self.pos += 4;
-        integer
+    if let Some(tech_level) = settings.tech_level() {
+        parts.push(format!("tech {}", tech_level));
     }
-
-    pub fn read_i16(&mut self) -> i16 {
-        let integer = LittleEndian::read_i16(&self.map[self.pos..self.pos+2]);
-        self.pos += 2;
-        integer
+    match settings.short_game() {
+        Some(true) => parts.push("short game".to_string()),
+        Some(false) => parts.push("long game".to_string()),
+        None => {}
     }
-
-    pub fn at_relative_offset(&self, offset: usize) -> u8 {
-        self.map[self.pos + offset]
+    match settings.crates() {
+        Some(true) => parts.push("crates".to_string()),
+        Some(false) => parts.push("no crates".to_string()),
+        None => {}
     }
+    parts.join(", ")
+}
 
-    pub fn read_u8(&mut self) -> u8 {        
-        let byte = self.map[self.pos];
-        self.pos += 1;
-        byte
+/// Draws the heatmap and markers of a plot on the map and saves it.
+fn render_plot(plot: &Plot, background: &DynamicImage, projection: &CellToPixel, font: &Font, styles: &StyleSheet, opts: &Opts, path: &Path) -> Result<(), Error> {
+    let mut image = background.clone();
+    let mut heatmap = Heatmap::new(projection.grid_size());
+    for &(x, y, _) in &plot.points {
+        heatmap.add(x, y);
     }
+    println!("Rendering heatmap of {} orders from {} replays.", heatmap.total(), plot.replays);
+    heatmap.render(&mut image, projection, opts.sigma);
 
-    pub fn pos(&self) -> usize {
-        self.pos
+    for marker in &plot.markers {
+        markers::draw(&mut image, projection, marker, 1.0);
     }
+    draw_legend(&mut image, font, plot, styles, opts);
 
-    pub fn set_pos(&mut self, pos: usize) {
-        self.pos = pos;
-    }
+    println!("Saving image {}.", path.display());
+    image.save(path).map_err(|e| Error::other(format!("Could not save {}: {}", path.display(), e)))
 }
 
-struct Player {
-    _client_id: i32,
-    name: String,
-    color: Rgba<u8>
+/// Runs `info`.
+fn info_command(opts: &InfoOpts) -> Result<(), Error> {
+    let mode = if opts.salvage { ReadMode::Salvage } else { ReadMode::Strict };
+    // the sync hashes of the replays of every game, to compare the replays its clients recorded
+    let mut games: BTreeMap<String, Vec<(PathBuf, Vec<SyncHash>)>> = BTreeMap::new();
+    for path in collect_replays(&opts.replays)? {
+        println!("{}", path.display());
+        let replay = match Replay::open_with_mode(&path, mode) {
+            Ok(replay) => replay,
+            Err(e) => {
+                println!("  could not be read: {}", e);
+                continue;
+            }
+        };
+        if let Some(warning) = replay.protocol_version().warning() {
+            println!("  Warning:  {}", warning);
+        }
+        let lobby = replay.lobby();
+        let sync_hashes: Vec<SyncHash> = replay.sync_hashes().map_while(Result::ok).collect();
+        if let Some(game_uid) = lobby.as_ref().and_then(|lobby| lobby.settings.game_uid.clone()) {
+            games.entry(game_uid).or_default().push((path.clone(), sync_hashes.clone()));
+        }
+        let metadata = match replay.metadata() {
+            Some(metadata) => metadata,
+            None => {
+                let header = replay.header();
+                println!("  no metadata, the game did not finish");
+                println!("  Game:     {} {}", header.mod_id.as_deref().unwrap_or("?"), header.version.as_deref().unwrap_or("?"));
+                println!("  Map:      {}", header.map_uid.as_deref().unwrap_or("?"));
+                // the players of the lobby, or else the clients of the handshake
+                let mut players: Vec<&Player> = replay.info().players.values().collect();
+                players.sort_by_key(|player| player.client_id);
+                for player in players {
+                    let team = if player.team == 0 { "-".to_string() } else { player.team.to_string() };
+                    let bot = player.bot_type.as_ref().map_or(String::new(), |bot_type| format!("  bot: {}", bot_type));
+                    println!("  #{:<2} {:<20} team {:<2} {:<10} spawn {:<2}{}",
+                             player.client_id, player.name, team, player.faction_id, player.spawn_point, bot);
+                }
+                if let Some(lobby) = &lobby {
+                    print_lobby_settings(&lobby.settings);
+                }
+                print_sync_hashes(&sync_hashes, replay.frame_duration(), None);
+                continue;
+            }
+        };
+        println!("  Game:     {} {}", metadata.mod_id, metadata.version);
+        println!("  Map:      {} ({})", metadata.map_title.as_deref().unwrap_or("?"), metadata.map_uid);
+        if let Some(start_time) = &metadata.start_time {
+            println!("  Started:  {} UTC", start_time);
+        }
+        let duration = metadata.duration().map_or("?".to_string(), format_duration);
+        match metadata.final_game_tick {
+            Some(ticks) => println!("  Duration: {} ({} ticks)", duration, ticks),
+            None => println!("  Duration: {}", duration)
+        }
+        if let Some(lobby) = &lobby {
+            print_lobby_settings(&lobby.settings);
+        }
+        let lost: Vec<i32> = metadata.players.values()
+            .filter(|player| player.outcome == Outcome::Lost)
+            .map(|player| player.client_id)
+            .collect();
+        let defeated = lobby.as_ref()
+            .and_then(|lobby| sync::defeated_clients(&sync_hashes, &lobby.playing_clients(), &lost))
+            .map(|defeated| defeated.into_iter()
+                .map(|(client, frame)| (metadata.players.get(&client).map_or_else(|| format!("#{}", client), |player| player.name.clone()), frame))
+                .collect());
+        print_sync_hashes(&sync_hashes, replay.frame_duration(), defeated);
+        let mut players: Vec<&Player> = metadata.players.values().collect();
+        players.sort_by_key(|player| player.client_id);
+        for player in players {
+            let team = if player.team == 0 { "-".to_string() } else { player.team.to_string() };
+            let bot = match (&player.bot_type, player.is_bot) {
+                (Some(bot_type), _) => format!("  bot: {}", bot_type),
+                (None, true) => "  bot".to_string(),
+                (None, false) => String::new()
+            };
+            println!("  #{:<2} {:<20} team {:<2} {:<10} {:<10} spawn {:<2} {:?}{}",
+                     player.client_id, player.name, team, player.faction_name, player.faction_id, player.spawn_point, player.outcome, bot);
+        }
+    }
+    for replays in games.values().filter(|replays| replays.len() > 1) {
+        let names: Vec<String> = replays.iter().map(|(path, _)| path.display().to_string()).collect();
+        let sync_hashes: Vec<SyncHash> = replays.iter().flat_map(|(_, sync_hashes)| sync_hashes.iter().copied()).collect();
+        match sync::first_desync(&sync_hashes) {
+            Some(frame) => println!("{} are of the same game and went out of sync at frame {}", names.join(", "), frame),
+            None => println!("{} are of the same game and stayed in sync", names.join(", "))
+        }
+    }
+    Ok(())
 }
 
-struct GameInformation {
-    version: String,
-    map_uid : String,
-    players: HashMap<i32, Player>
+/// A duration as `h:mm:ss`.
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
 }
 
-fn download_screenshot(screenshot_id : u32) -> String {
-    let client = reqwest::blocking::Client::new(); //TODO SHARE CLIENTS
-   
-    let url = format!("{}/{}", "https://resource.openra.net/screenshots", screenshot_id);
-    let response = client.get(&url).send();
-    let response = response.expect("response problem");
-
-    let fname = format!("{}.png", screenshot_id);
-    let mut dest = {
-        
-        // println!("file to download: '{}'", fname);
-        // let fname = tmp_dir.path().join(fname);
-        // println!("will be located under: '{:?}'", fname);
-        File::create(fname.clone()).expect("Could not create file")
+/// Prints what the sync hashes of a replay tell for `info`: how long the game ran, when players were defeated and whether its clients desynced.
+/// `defeated` are the names of the defeated players with the frame they were defeated at, when the defeats could be matched to them.
+fn print_sync_hashes(sync_hashes: &[SyncHash], frame_duration: Duration, defeated: Option<Vec<(String, i32)>>) {
+    let last_frame = match sync_hashes.iter().map(|sync_hash| sync_hash.frame).max() {
+        Some(frame) => frame,
+        None => return
     };
-    let content =  response.bytes().expect("Could not get bytes");
-    dest.write_all(content.bytes()).expect("Could not write");
-    fname
+    let time = |frame: i32| format_duration(frame_duration * frame.max(0) as u32);
+    println!("  Synced:   up to frame {} ({})", last_frame, time(last_frame));
+    match defeated {
+        Some(defeated) => for (name, frame) in defeated {
+            println!("  Defeated: {} at frame {} ({})", name, frame, time(frame));
+        },
+        None => {
+            let defeats = sync::defeats(sync_hashes);
+            if !defeats.is_empty() {
+                println!("  Defeated: players that can't be matched to clients, by their index in the game world (which starts with the map's non-playable players):");
+            }
+            for (player_index, frame) in defeats {
+                println!("            #{} at frame {} ({})", player_index, frame, time(frame));
+            }
+        }
+    }
+    if let Some(frame) = sync::first_desync(sync_hashes) {
+        println!("  Desync:   the clients went out of sync at frame {} ({})", frame, time(frame));
+    }
 }
 
-fn get_game_information(reader : &mut ReplayReader) -> GameInformation {
-
-    fn save_player(players: &mut HashMap<i32, Player>, client_id: Option<i32>, name: Option<&str>, color: Option<&str>) -> () {
-        let client_id_raw = client_id.expect("client id must be present");
-        let color =  i32::from_str_radix(color.expect("color must be present"), 16).expect("could not parse color");
-        let color_vector = [(color >> 16) as u8, (color >> 8) as u8, color as u8, 255];
-        players.insert(client_id_raw, Player {
-            _client_id: client_id_raw,
-            name: name.expect("name must be present").to_string(),
-            color: Rgba(color_vector)
-        });
-    }
- 
-    print!("Reading in metadata...");
-    let total_len = reader.len();
-    reader.set_pos(total_len - 8);
-    let metadata_len = reader.read_i32() as usize;
-    let marker = reader.read_i32();
-    if marker != -2 {
-        panic!("End marker NOK")
-    }
-    reader.set_pos(total_len - (8 + metadata_len + 8));
-    let start_marker = reader.read_i32();
-    if start_marker != -1 {
-        panic!("Expected start marker");
-    }
-    let metadata_version = reader.read_i32();
-    println!("version is {}", metadata_version);
-    let strlen = reader.read_i32() as usize;
-    /* this string is encoded differently than all other strings.. */
-    let metadata = reader.read_string_with_length(strlen);
-    // println!("metadata {}", metadata);
-    let lines: Vec<_> = metadata.lines().collect();
-    let mut client_id:Option<i32> = None;
-    let mut name: Option<&str> = None;
-    let mut color: Option<&str> = None;
-    let mut players: HashMap<i32, Player> = HashMap::new();
-    let mut map_uid = None;
-    let mut version = None;
-    for l in lines {
-        let trimmed = l.trim();
-        if trimmed.starts_with("Player@") {
-            if client_id.is_some() {
-                save_player(&mut players, client_id, name, color);
-
-                client_id = None;
-                name = None;
-                color = None;
-            }
-           
-
-        } else if trimmed.starts_with("ClientIndex:") {
-            client_id = Some(get_rhs(trimmed).parse().unwrap());
-        } else if trimmed.starts_with("Name:") {
-            name = Some(get_rhs(trimmed));
-        } else if trimmed.starts_with("Color:") {
-            color = Some(get_rhs(trimmed));
-        } else if trimmed.starts_with("MapUid:") {
-             map_uid = Some(get_rhs(trimmed));
-            println!("mapuid is {}", map_uid.unwrap());
-        } else if trimmed.starts_with("Version:") {
-            version = Some(get_rhs(trimmed));
-           println!("version is {}", version.unwrap());
-       }
-    }
-    save_player(&mut players, client_id, name, color);
-    reader.set_pos(0); //reset to beginning
-    GameInformation {
-        version: version.expect("game version must be present").to_string(),
-        map_uid : map_uid.expect("mapuid must be present").to_string(),
-        players
+/// Prints the game speed and lobby options of `info`.
+fn print_lobby_settings(settings: &LobbySettings) {
+    if let Some(speed) = &settings.game_speed {
+        println!("  Speed:    {}", speed);
+    }
+    let ruleset = ruleset(settings);
+    if !ruleset.is_empty() {
+        println!("  Ruleset:  {}", ruleset);
+    }
+    if !settings.options.is_empty() {
+        let options: Vec<String> = settings.options.iter().map(|(id, value)| format!("{}={}", id, value)).collect();
+        println!("  Options:  {}", options.join(" "));
     }
 }
 
-fn enforce_bounds<T>(val: T, min: T, max: T) -> T 
-where
-    T: Ord
-{
-    std::cmp::min(std::cmp::max(val, min), max)
+/// Runs `export`.
+fn export_command(opts: &ExportOpts) -> Result<(), Error> {
+    let replays = collect_replays(&opts.replays)?;
+    let format = if opts.format == "csv" { export::Format::Csv } else { export::Format::JsonLines };
+    let mode = if opts.salvage { ReadMode::Salvage } else { ReadMode::Strict };
+    match &opts.output {
+        Some(path) => export::export(&replays, format, mode, &mut BufWriter::new(File::create(path)?)),
+        None => export::export(&replays, format, mode, &mut io::stdout().lock())
+    }
 }
 
 fn main() -> Result<(), Error> {
-    const RED : Rgba<u8> = Rgba([255, 0 , 0, 255]);
-    const GREEN : Rgba<u8>= Rgba([0, 255 , 0, 255]);
-    const BLUE : Rgba<u8> = Rgba([0, 0 , 255, 255]);
-    let last_release_with_byte_for_flags: String = String::from("release-20200503"); //why cannot make this a constant ?
     let opts: Opts = Opts::parse();
-    println!("Reading replay file from : {}", opts.replay_filename);
-
-
-    let file = File::open(opts.replay_filename)?;
-    let map = unsafe { Mmap::map(&file)? };
-    let mut reader = ReplayReader::new(map);
-    let game_information = get_game_information(&mut reader);
-    let flags_are_short : bool = game_information.version == "{{DEV_VERSION}}" 
-                                || game_information.version.starts_with("playtest")
-                                || game_information.version > last_release_with_byte_for_flags;
-
-    let map_info = get_map_info(&game_information.map_uid).expect("Could not get map info");
-    
-    let screenshot_id = find_screenshot_id(map_info.id);
-    println!("screenshot id {:#?}", screenshot_id);
-    if screenshot_id.is_none() {
-        return Err(Error::new(ErrorKind::Other, "Unfortunately, no screenshot is available for download.. Maybe you could upload one ?"));
-    }
-    let screenshot = format!("{}.png", screenshot_id.unwrap());
-    if !Path::new(&screenshot).exists() {
-        println!("Screenshot not yet present - need to download it");
-        download_screenshot(screenshot_id.unwrap());
-    } else {
-        println!("Screenshot already there");
-    }
-    let mut image = read_screenshot(&screenshot);
-    let (screenshot_dim_x, screenshot_dim_y) = GenericImageView::dimensions(&image);
-    let x_ratio = screenshot_dim_x as f32 / map_info.width as f32;
-    let y_ratio = screenshot_dim_y as f32 / map_info.height as f32;
-
-    println!("Reading in frames..");
-    loop {
-        let client = reader.read_i32();
-        
-        if client == -1 {
-            break;
-        }        
-       
-        let packet_len = reader.read_i32() as usize;
-        let rpos: usize = reader.pos() + packet_len as usize;
-     
-        if packet_len == 5 && reader.at_relative_offset(4) == OrderType::Disconnect as u8 {
-            reader.set_pos(reader.pos() + packet_len);
-            continue; // disconnect
-        } else if packet_len >= 5 && reader.at_relative_offset(4) == OrderType::SyncHash as u8 {
-            reader.set_pos(reader.pos() + packet_len);
-            continue; // sync
-        }
+    let cache = MapCache::new(opts.cache_dir.as_deref())?;
+    match &opts.command {
+        Some(Command::Cache(cache_opts)) => return cache_command(&cache_opts.command, &cache),
+        Some(Command::Serve(serve_opts)) => return server::serve(cache, serve_opts.listen),
+        Some(Command::Export(export_opts)) => return export_command(export_opts),
+        Some(Command::Info(info_opts)) => return info_command(info_opts),
+        None => {}
+    }
 
-        let _frame = reader.read_i32();
-        while reader.pos() < rpos {
-            let ordertypebyte = reader.read_u8();
-            let ordertype = OrderType::try_from(ordertypebyte).expect("Could not convert byte to ordertype");
-            match ordertype {
-                OrderType::Handshake => {
-                   
-                        let _name = reader.read_string();
-                        let _targetstring = reader.read_string();
-                },
-                OrderType::Fields => {
-                    let order = reader.read_string();
-                    
-                    let flags;
-                    if flags_are_short {
-                         flags = reader.read_i16();
-                    } else {
-                        flags = reader.read_u8() as i16;
-                    }
-                    // println!("order {}, flags {:#02x}", order, flags);
-
-                    
-                    if flags & OrderFields::Subject as i16 > 0 {
-                        let _subject_id = reader.read_u32();
-                    }
-                    if flags & OrderFields::Target as i16 > 0 {
-                        let target_type_byte = reader.read_u8();
-                        let target_type = TargetType::try_from(target_type_byte).expect("Could not convert byte to target type");
-                        // println!("target type is {:?}", target_type);
-                        match target_type {
-                            TargetType::Actor => {
-                                let _actor_id = reader.read_u32();
-
-                            },
-                            TargetType::FrozenActor => {
-                                let _player_actor_id =  reader.read_u32();
-                                let _frozen_actor_id =  reader.read_u32();
-                            },
-                            TargetType::Terrain => {
-                                   if flags & OrderFields::TargetIsCell as i16 > 0 {
-                                        let cell =  reader.read_u32();
-                                        let world_x = (cell >> 20) as i16;
-                                        let world_y = ((cell >> 8) & 0xFFF) as i16;
-                                        let _world_z = cell as u8;
-                                        let _subcell = reader.read_u8();
-                                       
-                                        let player = game_information.players.get(&client).expect("unknown client-id");
-                                        
-                                        
-                                        //improve https://users.rust-lang.org/t/how-do-i-copy-contents-of-image-into-an-image-buffer/33206/5
-
-                                        for xd in -4..5 {
-                                            for yd in -4..5 {
-                                                let x = (x_ratio / 2.0 + enforce_bounds(world_x, 0, (map_info.width - 1) as i16) as f32 * x_ratio) as i16 + xd;
-                                                let y = (y_ratio / 2.0 + enforce_bounds(world_y, 0, (map_info.height - 1) as i16) as f32 * y_ratio) as i16 + yd;
-                                                let pixel = if 2 < i16::abs(xd) || 2 < i16::abs(yd) {
-                                                    if order == "AttackMove" || order == "AssaultMove" || order == "ForceAttack" || order == "Move" || order == "PlaceBuilding" {
-                                                        Some(player.color)
-                                                    } else {
-                                                        None
-                                                    }    
-                                                } else {
-                                                    if order == "AttackMove" || order == "AssaultMove" || order == "ForceAttack" {                                                        
-                                                        Some(RED)
-                                                    } else if order == "Move" {
-                                                        Some(GREEN)
-                                                    }  else if order == "PlaceBuilding" {
-                                                        Some(BLUE)
-                                                    } else if order == "SetRallyPoint" || order == "Harvest" || order == "BeginMinefield" || order == "PlaceMinefield" {
-                                                        // panic!("order {}", order);
-                                                        None
-                                                    } else {
-                                                        None
-                                                    }
-                                                                                                    
-                                                };
-                                                if pixel.is_some() {
-                                                    image.as_mut_rgba8().unwrap().put_pixel(x as u32, y as u32, pixel.unwrap());
-                                                }
-                                            }
-                                        }
-                                        
-                                        
-                                   } else {
-                                        let _x =  reader.read_u32() as i16;
-                                        let _y =  reader.read_u32() as i16;
-                                        let _z = reader.read_u32() as u8;
-                                        
-                                   }
-                            },
-                            TargetType::Invalid => {}
-                        }
-                    }
-                    if flags & OrderFields::TargetString as i16 > 0 {
-                        let _target_string = reader.read_string();
-                        // println!("target_string {}", target_string);                        
-                    }
-                    if flags & OrderFields::ExtraActors as i16 > 0 {
-                        let count =  reader.read_u32();
-                        let mut vec = Vec::new();
-                        for _ in 0..count {
-                            let tmp =  reader.read_u32();
-                            vec.push(tmp)
-                        }
-                    }
-                    if flags & OrderFields::ExtraLocation as i16 > 0 {
-                        let _pos =  reader.read_i32();                        
-                    }
-                    if flags & OrderFields::ExtraData as i16 > 0 {
-                        let _extradata =  reader.read_u32();
-                    }
-                    if flags & OrderFields::Grouped as i16 > 0 {
-                        let count =  reader.read_i32();
-                        let mut vec = Vec::new();
-                        for _ in 0..count {
-                            let tmp =  reader.read_u32();
-                            vec.push(tmp)
-                        }
-                    }
-                },
-                OrderType::SyncHash => {
-                    //noop
-                    println!("sync hash");
-                },
-                _ => {
-                    println!("ordertype {:?} not supported", ordertype);
+    let styles = match &opts.style {
+        Some(path) => StyleSheet::from_file(path)?,
+        None => StyleSheet::builtin()
+    };
+    let replays = collect_replays(&opts.replays)?;
+    if replays.is_empty() {
+        return Err(Error::other("No replay files found"));
+    }
+
+    let mode = if opts.salvage { ReadMode::Salvage } else { ReadMode::Strict };
+    let mut opened = Vec::new();
+    for replay_filename in replays {
+        println!("Reading replay file from : {}", replay_filename.display());
+        match Replay::open_with_mode(&replay_filename, mode) {
+            Ok(replay) => {
+                if let Some(warning) = replay.protocol_version().warning() {
+                    println!("Warning: {}", warning);
                 }
+                opened.push((replay_filename, replay))
+            },
+            Err(e) => println!("Skipping {}: {}", replay_filename.display(), e)
+        }
+    }
 
-            }
+    // replays salvaged without metadata or lobby don't know their map, they are assumed to be on the same map as the others
+    let map_uid = opened.iter()
+        .map(|(_, replay)| &replay.info().map_uid)
+        .find(|map_uid| !map_uid.is_empty())
+        .cloned();
+    let MapBackground { bounds, grid, image } = match (&opts.map, &map_uid) {
+        (Some(path), _) => local_background(path, &opts)?,
+        (None, Some(map_uid)) => {
+            let resources = ResourceClient::new(&opts.resource_url, Duration::from_secs(opts.timeout), opts.retries)?;
+            download_background(map_uid, &resources, &cache, &opts)?
+        },
+        (None, None) => return Err(Error::other("None of the replays tells which map was played, use --map"))
+    };
+    let projection = CellToPixel::new(bounds, grid, GenericImageView::dimensions(&image));
+    let mut combined = Plot::default();
+    let mut per_player: BTreeMap<String, Plot> = BTreeMap::new();
+
+    for (replay_filename, replay) in opened {
+        let game_information = replay.info();
+        match &map_uid {
+            _ if game_information.map_uid.is_empty() => {
+                println!("{} does not tell its map, assuming it was played on the same map", replay_filename.display());
+            },
+            Some(uid) if game_information.map_uid != *uid => {
+                println!("Skipping {}: it was played on map {}, not on {}", replay_filename.display(), game_information.map_uid, uid);
+                continue;
+            },
+            _ => {}
         }
-        
-        //return Ok(());
-    }        
-    println!("Done.");
 
+        let ruleset = replay.lobby().map(|lobby| ruleset(&lobby.settings)).filter(|ruleset| !ruleset.is_empty());
+        if let Some(ruleset) = &ruleset {
+            println!("Played with {}", ruleset);
+        }
 
+        println!("Reading in frames..");
+        let frame_duration = replay.frame_duration();
+        let from = opts.from.map_or(i32::MIN, |time| time.to_frame(frame_duration));
+        let to = opts.to.map_or(i32::MAX, |time| time.to_frame(frame_duration));
+        let mut orders = replay.orders();
+        let mut tracker = ActorTracker::new(grid);
+        let mut located = Vec::new();
+        let mut error = None;
+        for order in &mut orders {
+            let order = match order {
+                Ok(order) => order,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            };
+            let location = match order.target_location(grid) {
+                Some(cell) if matches!(order.target, Target::Position(_)) => Some((cell, MarkerKind::Position)),
+                Some(cell) => Some((cell, MarkerKind::Cell)),
+                None => tracker.resolve(&order.target)
+                    .filter(|position| opts.actor_targets == "all" || (opts.actor_targets == "buildings" && position.confidence == Confidence::Placement))
+                    .map(|position| (position.cell, MarkerKind::Actor(position.confidence)))
+            };
+            tracker.observe(&order);
+            if order.frame < from || order.frame > to {
+                continue;
+            }
+            if let Some((cell, kind)) = location {
+                located.push((order, cell, kind));
+            }
+        }
+        if let Some(e) = error {
+            println!("Skipping {}: {}", replay_filename.display(), e);
+            continue;
+        }
+        let stats = orders.into_stats();
+        if let Some(e) = &stats.error {
+            println!("Stopped reading {} early ({}): salvaged {} orders in {} frames, up to frame {}",
+                     replay_filename.display(), e, stats.orders, stats.frames, stats.last_frame);
+        }
+        if stats.skipped_packets > 0 {
+            println!("Skipped {} packets of unknown kinds in {}", stats.skipped_packets, replay_filename.display());
+        }
+        let selected: Vec<&Player> = game_information.players.values().filter(|player| is_selected(player, &opts)).collect();
+        for (order, cell, kind) in located {
+            let player = game_information.players.get(&order.client);
+            // orders of unknown clients are only plotted when no players are selected
+            if !player.map_or(opts.player.is_empty() && opts.team.is_empty(), |player| is_selected(player, &opts)) {
+                continue;
+            }
+            let style = styles.style(&order.order, kind);
+            if style.is_some_and(|style| !style.include) {
+                continue;
+            }
+            // orders outside the bounds can't be shown, counting them on the edge would make it look busy
+            let (x, y) = match projection.to_grid_within(cell) {
+                Some(position) => position,
+                None => continue
+            };
+            // the extra location is only drawn with the marker, counting it too would count the order twice
+            let point = (x, y, order.frame);
+            let marker = match style {
+                Some(style) if opts.markers && style.shape != Shape::None => {
+                    let footprint = match (order.order.as_str(), &order.target_string) {
+                        ("PlaceBuilding", Some(building)) => footprint(&game_information.mod_id, building),
+                        _ => None
+                    };
+                    Some(Marker {
+                        frame: order.frame,
+                        cell,
+                        kind,
+                        color: player.map_or(GRAY, |player| Rgba(player.color)),
+                        fill: style.color,
+                        shape: style.shape,
+                        size: style.size,
+                        footprint,
+                        extra_location: order.extra_location
+                    })
+                },
+                _ => None
+            };
+            if let Some(player) = player.filter(|_| opts.per_player) {
+                let plot = per_player.entry(player.name.clone()).or_default();
+                plot.points.push(point);
+                plot.markers.extend(marker.clone());
+            }
+            combined.points.push(point);
+            combined.markers.extend(marker);
+        }
+        println!("Done.");
+
+        for player in selected {
+            if opts.per_player {
+                let plot = per_player.entry(player.name.clone()).or_default();
+                plot.players.push(player.clone());
+                plot.replays += 1;
+                plot.rulesets.extend(ruleset.clone());
+            }
+            combined.players.push(player.clone());
+        }
+        combined.replays += 1;
+        combined.rulesets.extend(ruleset);
+    }
+
+    if combined.replays == 0 {
+        return Err(Error::other("None of the replays could be read"));
+    }
     let font_data: &[u8] = include_bytes!("/usr/share/fonts/truetype/dejavu/DejaVuSansMono.ttf");
     let font: Font<'static> = Font::try_from_bytes(font_data).expect("could not load font");
 
-    for (i, (_, player)) in game_information.players.iter().enumerate() {
-        imageproc::drawing::draw_text_mut(&mut image, player.color, 10, 10 + i as u32 * 50, Scale {x: 40.0, y: 40.0},  &font, &player.name);
+    if let Some(path) = &opts.animate {
+        println!("Rendering animation of {} replay frames per animation frame.", opts.window);
+        let settings = animation::Settings { window: opts.window, fade: opts.fade, delay: opts.frame_delay, sigma: opts.sigma };
+        animation::render(path, &image, &projection, &combined.points, &combined.markers, &settings, |frame_image, first, last| {
+            draw_legend(frame_image, &font, &combined, &styles, &opts);
+            let (_, height) = GenericImageView::dimensions(frame_image);
+            imageproc::drawing::draw_text_mut(frame_image, WHITE, 10, height.saturating_sub(50), Scale {x: 40.0, y: 40.0},  &font, &format!("Frame {}-{}", first, last));
+        })?;
     }
-    imageproc::drawing::draw_text_mut(&mut image, RED, 500, 10, Scale {x: 40.0, y: 40.0},  &font, "Attack/AssaultMove");
-    imageproc::drawing::draw_text_mut(&mut image, GREEN, 500, 60, Scale {x: 40.0, y: 40.0},  &font, "Move");
-    imageproc::drawing::draw_text_mut(&mut image, BLUE, 500, 110, Scale {x: 40.0, y: 40.0},  &font, "PlaceBuilding");
-
-    // image.as_mut_rgba8().unwrap().draw_text(Rgba([255, 0 , 0, 255]), 10, 10, Scale {x: 40.0, y: 40.0}, "hello world");
-    println!("Saving image.");
 
-    image.save("output.png").expect("Could not save output image");
+    render_plot(&combined, &image, &projection, &font, &styles, &opts, Path::new(&opts.output))?;
+    for (name, plot) in &per_player {
+        render_plot(plot, &image, &projection, &font, &styles, &opts, &player_output(&opts.output, name))?;
+    }
     println!("Finished");
 
     Ok(())
 }
-
-fn get_rhs(line: &str) -> &str {
-   line.rsplitn(2, ' ').next().unwrap()
-}
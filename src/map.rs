@@ -0,0 +1,173 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use crate::error::MapError;
+use crate::miniyaml;
+
+/// The playable area of a map, in cells. Everything outside of it is never shown in game nor in the previews.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Bounds {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32
+}
+
+/// How cells are laid out: square cells (Red Alert, Tiberian Dawn, Dune 2000) or diamonds (Tiberian Sun).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MapGridType {
+    Rectangular,
+    RectangularIsometric
+}
+
+/// An OpenRA map, either packed as `.oramap` (a zip file) or unpacked in a folder.
+pub struct MapPackage {
+    pub title: String,
+    pub tileset: String,
+    /// Width and height of the whole map, in cells.
+    pub map_size: (u16, u16),
+    pub bounds: Bounds,
+    files: HashMap<String, Vec<u8>>
+}
+
+impl MapPackage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<MapPackage, MapError> {
+        let path = path.as_ref();
+        let mut files = HashMap::new();
+        if path.is_dir() {
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    files.insert(entry.file_name().to_string_lossy().into_owned(), fs::read(entry.path())?);
+                }
+            }
+        } else {
+            let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+            for i in 0..archive.len() {
+                let mut file = archive.by_index(i)?;
+                if file.is_file() {
+                    let mut contents = Vec::new();
+                    file.read_to_end(&mut contents)?;
+                    files.insert(file.name().to_string(), contents);
+                }
+            }
+        }
+        MapPackage::from_files(files)
+    }
+
+    fn from_files(files: HashMap<String, Vec<u8>>) -> Result<MapPackage, MapError> {
+        let yaml = files.get("map.yaml").ok_or(MapError::MissingFile("map.yaml"))?;
+        let yaml = String::from_utf8_lossy(yaml).into_owned();
+
+        let nodes = miniyaml::parse(&yaml)?;
+        let value = |key| nodes.iter().find(|node| node.key == key).map(|node| node.value.clone());
+        let map_size = match value("MapSize") {
+            Some(size) => {
+                let size = parse_numbers::<u16>("MapSize", &size, 2)?;
+                Some((size[0], size[1]))
+            },
+            None => None
+        };
+        let bounds = match value("Bounds") {
+            Some(bounds) => {
+                let b = parse_numbers::<i32>("Bounds", &bounds, 4)?;
+                Some(Bounds { x: b[0], y: b[1], width: b[2], height: b[3] })
+            },
+            None => None
+        };
+
+        let map_size = map_size.ok_or(MapError::MissingField("MapSize"))?;
+        Ok(MapPackage {
+            title: value("Title").unwrap_or_default(),
+            tileset: value("Tileset").unwrap_or_default(),
+            map_size,
+            bounds: bounds.unwrap_or(Bounds { x: 0, y: 0, width: map_size.0 as i32, height: map_size.1 as i32 }),
+            files
+        })
+    }
+
+    pub fn file(&self, name: &str) -> Option<&[u8]> {
+        self.files.get(name).map(|f| f.as_slice())
+    }
+
+    /// The PNG preview the editor saved with the map, which shows the area within `bounds`.
+    pub fn preview(&self) -> Option<&[u8]> {
+        self.file("map.png")
+    }
+
+    /// Decodes the tile and resource layers stored in `map.bin`.
+    pub fn tiles(&self) -> Result<TileLayers, MapError> {
+        let data = self.file("map.bin").ok_or(MapError::MissingFile("map.bin"))?;
+        let truncated = || MapError::InvalidField { field: "map.bin", value: format!("truncated ({} bytes)", data.len()) };
+        let u16_at = |offset: usize| data.get(offset..offset + 2).map(LittleEndian::read_u16).ok_or_else(truncated);
+        let u32_at = |offset: usize| data.get(offset..offset + 4).map(LittleEndian::read_u32).ok_or_else(truncated);
+
+        let format = *data.first().ok_or_else(truncated)?;
+        let width = u16_at(1)?;
+        let height = u16_at(3)?;
+        if (width, height) != self.map_size {
+            return Err(MapError::InvalidField { field: "map.bin", value: format!("size {}x{} differs from MapSize", width, height) });
+        }
+        let cells = width as usize * height as usize;
+        let (tiles_offset, resources_offset) = match format {
+            1 => (5, 5 + 3 * cells),
+            2 => (u32_at(5)? as usize, u32_at(13)? as usize),
+            _ => return Err(MapError::InvalidField { field: "map.bin", value: format!("unsupported format {}", format) })
+        };
+
+        // cells are stored column by column
+        let mut tiles = vec![(0, 0); cells];
+        let mut resources = vec![(0, 0); cells];
+        for u in 0..width as usize {
+            for v in 0..height as usize {
+                let i = u * height as usize + v;
+                let tile_offset = tiles_offset + 3 * i;
+                tiles[v * width as usize + u] = (u16_at(tile_offset)?, *data.get(tile_offset + 2).ok_or_else(truncated)?);
+                if resources_offset > 0 {
+                    let resource_offset = resources_offset + 2 * i;
+                    let resource = data.get(resource_offset..resource_offset + 2).ok_or_else(truncated)?;
+                    resources[v * width as usize + u] = (resource[0], resource[1]);
+                }
+            }
+        }
+        Ok(TileLayers {
+            width,
+            height,
+            tiles,
+            resources
+        })
+    }
+}
+
+/// The terrain of a map, cell by cell.
+pub struct TileLayers {
+    pub width: u16,
+    pub height: u16,
+    tiles: Vec<(u16, u8)>,
+    resources: Vec<(u8, u8)>
+}
+
+impl TileLayers {
+    /// The (template id, index within the template) of the tile at map position (u, v).
+    pub fn tile(&self, u: u16, v: u16) -> (u16, u8) {
+        self.tiles[v as usize * self.width as usize + u as usize]
+    }
+
+    /// The (resource type, density) at map position (u, v). Type 0 means no resources.
+    pub fn resource(&self, u: u16, v: u16) -> (u8, u8) {
+        self.resources[v as usize * self.width as usize + u as usize]
+    }
+}
+
+fn parse_numbers<T: std::str::FromStr>(field: &'static str, value: &str, count: usize) -> Result<Vec<T>, MapError> {
+    let numbers = value.split(',')
+        .map(|n| n.trim().parse::<T>())
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|_| MapError::InvalidField { field, value: value.to_string() })?;
+    if numbers.len() != count {
+        return Err(MapError::InvalidField { field, value: value.to_string() });
+    }
+    Ok(numbers)
+}
@@ -0,0 +1,142 @@
+use image::{DynamicImage, GenericImageView, Rgba};
+use imageproc::drawing::BresenhamLineIter;
+use openra_heatmap::{CPos, Confidence};
+use serde::Deserialize;
+use crate::projection::CellToPixel;
+
+pub const WHITE : Rgba<u8> = Rgba([255, 255 , 255, 255]);
+pub const GRAY : Rgba<u8> = Rgba([128, 128 , 128, 255]);
+
+/// How the location of an order was found.
+#[derive(Clone, Copy)]
+pub enum MarkerKind {
+    Cell,
+    /// A world position rather than a cell (attack ground, support powers).
+    Position,
+    /// The tracked position of the targeted actor.
+    Actor(Confidence)
+}
+
+/// The shape of a marker, see default-style.toml.
+#[derive(Deserialize, Clone, Copy, Eq, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    #[default]
+    Square,
+    Hollow,
+    Circle,
+    Cross,
+    None
+}
+
+/// An order to draw on top of the heatmap.
+#[derive(Clone)]
+pub struct Marker {
+    pub frame: i32,
+    pub cell: CPos,
+    pub kind: MarkerKind,
+    /// The color of the player.
+    pub color: Rgba<u8>,
+    /// The color of the order, from its style.
+    pub fill: Rgba<u8>,
+    pub shape: Shape,
+    /// Width in pixels.
+    pub size: u32,
+    /// Size of the placed building, for PlaceBuilding orders of buildings in the footprint table.
+    pub footprint: Option<(i32, i32)>,
+    /// A second cell, e.g. the other end of a minefield.
+    pub extra_location: Option<CPos>
+}
+
+/// Draws a marker, see `draw_marker`, `draw_footprint` and `draw_extra_location`.
+/// With an opacity below 1 it is blended with what is below it, to fade it out.
+pub fn draw(image: &mut DynamicImage, projection: &CellToPixel, marker: &Marker, opacity: f32) {
+    if let Some(extra_location) = marker.extra_location {
+        draw_extra_location(image, projection, marker.cell, extra_location, marker.color, opacity);
+    }
+    match marker.footprint {
+        Some(size) => draw_footprint(image, projection, marker.cell, size, marker.fill, marker.color, opacity),
+        None => draw_marker(image, projection, marker, opacity)
+    }
+}
+
+/// Draws the marker of a single order: its shape in the color of the order's style,
+/// surrounded by a border in the player's color.
+/// The inside of actors that were only ordered to move to where they are, is left hollow.
+fn draw_marker(image: &mut DynamicImage, projection: &CellToPixel, marker: &Marker, opacity: f32) {
+    let half = (marker.size / 2) as i32;
+    let inner = (half - 2).max(0);
+    let hollow = marker.shape == Shape::Hollow || matches!(marker.kind, MarkerKind::Actor(Confidence::Destination));
+    let (center_x, center_y) = projection.to_pixel(marker.cell);
+    for xd in -half..=half {
+        for yd in -half..=half {
+            // the distance from the center, in the metric of the shape
+            let distance = match marker.shape {
+                Shape::None => return,
+                Shape::Square | Shape::Hollow => i32::max(xd.abs(), yd.abs()),
+                Shape::Circle => ((xd * xd + yd * yd) as f32).sqrt().round() as i32,
+                Shape::Cross if (xd.abs() - yd.abs()).abs() <= 1 => i32::max(xd.abs(), yd.abs()),
+                Shape::Cross => continue
+            };
+            let pixel = if distance > half {
+                continue;
+            } else if distance > inner {
+                marker.color
+            } else if hollow && distance < inner {
+                continue;
+            } else {
+                marker.fill
+            };
+            blend(image, center_x as i32 + xd, center_y as i32 + yd, pixel, opacity);
+        }
+    }
+}
+
+/// Fills the cells covered by a placed building, outlined in the player's color.
+fn draw_footprint(image: &mut DynamicImage, projection: &CellToPixel, top_left: CPos, (width, height): (i32, i32), fill: Rgba<u8>, player_color: Rgba<u8>, opacity: f32) {
+    let (cell_width, cell_height) = projection.cell_size();
+    let (half_width, half_height) = ((cell_width / 2.0).max(1.0) as i32, (cell_height / 2.0).max(1.0) as i32);
+    for dx in 0..width {
+        for dy in 0..height {
+            let (center_x, center_y) = projection.to_pixel(CPos { x: top_left.x + dx, y: top_left.y + dy, layer: top_left.layer });
+            for px in -half_width..half_width {
+                for py in -half_height..half_height {
+                    let outline = (px == -half_width && dx == 0) || (px == half_width - 1 && dx == width - 1)
+                        || (py == -half_height && dy == 0) || (py == half_height - 1 && dy == height - 1);
+                    let (x, y) = (center_x as i32 + px, center_y as i32 + py);
+                    if outline {
+                        blend(image, x, y, player_color, opacity);
+                    } else {
+                        blend(image, x, y, fill, opacity * 0.5);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Connects an order's target with its extra location, which is marked with a small square.
+fn draw_extra_location(image: &mut DynamicImage, projection: &CellToPixel, cell: CPos, extra_location: CPos, player_color: Rgba<u8>, opacity: f32) {
+    let (x, y) = projection.to_pixel(extra_location);
+    for (lx, ly) in BresenhamLineIter::new(projection.to_pixel(cell), (x, y)) {
+        blend(image, lx, ly, player_color, opacity);
+    }
+    for xd in -1..2 {
+        for yd in -1..2 {
+            blend(image, x as i32 + xd, y as i32 + yd, player_color, opacity);
+        }
+    }
+}
+
+/// Mixes a color into the pixel at (x, y), if it lies within the image.
+fn blend(image: &mut DynamicImage, x: i32, y: i32, color: Rgba<u8>, opacity: f32) {
+    let (dim_x, dim_y) = GenericImageView::dimensions(image);
+    if x < 0 || y < 0 || x as u32 >= dim_x || y as u32 >= dim_y {
+        return;
+    }
+    //improve https://users.rust-lang.org/t/how-do-i-copy-contents-of-image-into-an-image-buffer/33206/5
+    let pixel = image.as_mut_rgba8().unwrap().get_pixel_mut(x as u32, y as u32);
+    for c in 0..3 {
+        pixel[c] = (pixel[c] as f32 * (1.0 - opacity) + color[c] as f32 * opacity) as u8;
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::time::Duration;
+use crate::error::ReplayError;
+use crate::lobby::parse_color;
+use crate::miniyaml::{self, MiniYamlNode};
+
+/// The color of players whose color is missing or can't be read.
+pub(crate) const GRAY: [u8; 4] = [128, 128, 128, 255];
+
+/// How a game ended for a player.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Outcome {
+    /// Still playing when the replay ended, or an observer.
+    Undefined,
+    Won,
+    Lost
+}
+
+#[derive(Debug, Clone)]
+pub struct Player {
+    pub client_id: i32,
+    pub name: String,
+    /// RGBA
+    pub color: [u8; 4],
+    /// 0 when the player was not in a team.
+    pub team: i32,
+    /// The faction the player played, e.g. `Soviet`, after a random faction was resolved.
+    pub faction_name: String,
+    /// The internal name of the faction, e.g. `russia`.
+    pub faction_id: String,
+    /// 0 when the spawn point was picked at random and not recorded.
+    pub spawn_point: i32,
+    pub outcome: Outcome,
+    pub is_bot: bool,
+    /// The kind of AI, for bots whose type was recorded.
+    pub bot_type: Option<String>,
+    /// The fingerprint of the player's OpenRA forum account, for authenticated players.
+    pub fingerprint: Option<String>
+}
+
+/// Everything the metadata trailer of a replay tells about the game.
+#[derive(Debug, Clone)]
+pub struct ReplayMetadata {
+    /// The mod the game was played with, e.g. `ra`, `cnc`, `d2k` or `ts`. Empty when unknown.
+    pub mod_id: String,
+    pub version: String,
+    pub map_uid: String,
+    pub map_title: Option<String>,
+    /// When the game started and ended, as written by OpenRA (`yyyy-MM-dd HH-mm-ss`, UTC).
+    pub start_time: Option<String>,
+    pub end_time: Option<String>,
+    /// The last tick of the game.
+    pub final_game_tick: Option<i32>,
+    /// By client index.
+    pub players: HashMap<i32, Player>
+}
+
+impl ReplayMetadata {
+    /// Reads the metadata, a MiniYaml document with a `Root` node holding the game's fields,
+    /// followed by a `Player@<n>` node for every player.
+    pub fn parse(yaml: &str) -> Result<ReplayMetadata, ReplayError> {
+        let nodes = miniyaml::parse(yaml)?;
+        let root = nodes.iter().find(|node| node.key == "Root").ok_or(ReplayError::MissingMetadata { field: "Root" })?;
+        let mut players = HashMap::new();
+        for node in nodes.iter().filter(|node| node.name() == "Player") {
+            let player = read_player(node)?;
+            players.insert(player.client_id, player);
+        }
+        let text = |key| root.value_of(key).filter(|value| !value.is_empty()).map(|value| value.to_string());
+        Ok(ReplayMetadata {
+            mod_id: text("Mod").unwrap_or_default(),
+            version: text("Version").ok_or(ReplayError::MissingMetadata { field: "Version" })?,
+            map_uid: text("MapUid").ok_or(ReplayError::MissingMetadata { field: "MapUid" })?,
+            map_title: text("MapTitle"),
+            start_time: text("StartTimeUtc"),
+            end_time: text("EndTimeUtc"),
+            final_game_tick: root.value_of("FinalGameTick").and_then(|tick| tick.parse().ok()),
+            players
+        })
+    }
+
+    /// How long the game took, from its start and end time.
+    pub fn duration(&self) -> Option<Duration> {
+        let start = parse_utc(self.start_time.as_ref()?)?;
+        let end = parse_utc(self.end_time.as_ref()?)?;
+        end.checked_sub(start).map(Duration::from_secs)
+    }
+}
+
+fn read_player(node: &MiniYamlNode) -> Result<Player, ReplayError> {
+    let required = |field: &'static str| node.value_of(field).ok_or(ReplayError::MissingMetadata { field });
+    let number = |field: &'static str| -> Result<i32, ReplayError> {
+        match node.value_of(field) {
+            Some(value) => value.parse().map_err(|_| ReplayError::InvalidMetadata { field, value: value.to_string() }),
+            None => Ok(0)
+        }
+    };
+    let text = |field| node.value_of(field).filter(|value| !value.is_empty()).map(|value| value.to_string());
+
+    let client_id = required("ClientIndex")?;
+    let client_id = client_id.parse().map_err(|_| ReplayError::InvalidMetadata { field: "ClientIndex", value: client_id.to_string() })?;
+    Ok(Player {
+        client_id,
+        name: required("Name")?.to_string(),
+        // a color we can't read only makes the player gray, rather than the replay unreadable
+        color: node.value_of("Color").and_then(parse_color).unwrap_or(GRAY),
+        team: number("Team")?,
+        faction_name: text("FactionName").unwrap_or_default(),
+        faction_id: text("FactionId").unwrap_or_default(),
+        spawn_point: number("SpawnPoint")?,
+        outcome: match node.value_of("Outcome") {
+            Some("Won") => Outcome::Won,
+            Some("Lost") => Outcome::Lost,
+            _ => Outcome::Undefined
+        },
+        is_bot: node.value_of("IsBot").is_some_and(|value| value.eq_ignore_ascii_case("true")),
+        bot_type: text("BotType"),
+        fingerprint: text("Fingerprint")
+    })
+}
+
+/// Seconds since the Unix epoch of a `yyyy-MM-dd HH-mm-ss` timestamp (colons between the time fields are accepted too).
+fn parse_utc(timestamp: &str) -> Option<u64> {
+    let (date, time) = timestamp.trim().split_once(' ')?;
+    let date: Vec<i64> = date.split('-').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let time: Vec<i64> = time.split(&['-', ':'][..]).map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let (&[year, month, day], &[hours, minutes, seconds]) = (date.as_slice(), time.as_slice()) else {
+        return None;
+    };
+    // days from civil, http://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    u64::try_from(days * 86400 + hours * 3600 + minutes * 60 + seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// As written by OpenRA's `GameInformation.Serialize`.
+    const METADATA: &str = "Root:
+\tMod: ra
+\tVersion: release-20210321
+\tMapUid: 2e4de7bc8d2acd9e0cd6ed4ae5f09ae4e8bd4a3c
+\tMapTitle: Behind The Veil
+\tStartTimeUtc: 2021-04-02 19-51-28
+\tEndTimeUtc: 2021-04-02 20-12-40
+\tFinalGameTick: 31800
+Player@0:
+\tClientIndex: 0
+\tName: Alice Smith
+\tIsHuman: True
+\tIsBot: False
+\tFactionName: Allies
+\tFactionId: england
+\tColor: FF0000
+\tTeam: 1
+\tSpawnPoint: 3
+\tIsRandomFaction: False
+\tIsRandomSpawnPoint: False
+\tFingerprint: 7b3bd5c47b3bd5c4
+\tOutcome: Won
+\tOutcomeTimestampUtc: 2021-04-02 20-12-40
+Player@1:
+\tClientIndex: 2
+\tName: Normal AI
+\tIsHuman: False
+\tIsBot: True
+\tBotType: normal
+\tFactionName: Soviet
+\tFactionId: russia
+\tColor: 0000FF80
+\tTeam: 0
+\tSpawnPoint: 1
+\tOutcome: Lost
+";
+
+    #[test]
+    fn reads_players_next_to_root() {
+        let metadata = ReplayMetadata::parse(METADATA).unwrap();
+        assert_eq!(metadata.version, "release-20210321");
+        assert_eq!(metadata.map_title.as_deref(), Some("Behind The Veil"));
+        assert_eq!(metadata.duration(), Some(Duration::from_secs(21 * 60 + 12)));
+        assert_eq!(metadata.players.len(), 2);
+
+        let alice = &metadata.players[&0];
+        assert_eq!(alice.name, "Alice Smith");
+        assert_eq!(alice.color, [255, 0, 0, 255]);
+        assert_eq!((alice.team, alice.spawn_point, alice.outcome), (1, 3, Outcome::Won));
+        assert_eq!(alice.fingerprint.as_deref(), Some("7b3bd5c47b3bd5c4"));
+
+        let bot = &metadata.players[&2];
+        assert!(bot.is_bot);
+        assert_eq!(bot.bot_type.as_deref(), Some("normal"));
+        assert_eq!(bot.faction_id, "russia");
+        assert_eq!(bot.color, [0, 0, 255, 128]);
+    }
+
+    #[test]
+    fn bad_color_is_gray() {
+        let metadata = ReplayMetadata::parse(&METADATA.replace("FF0000", "red")).unwrap();
+        assert_eq!(metadata.players[&0].color, [128, 128, 128, 255]);
+    }
+}
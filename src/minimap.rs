@@ -0,0 +1,139 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use openra_heatmap::{miniyaml, Bounds, MapGridType, MiniYamlNode, TileLayers};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Error;
+
+/// Terrain colors per tile template, used to paint a minimap when a map has no screenshot.
+pub struct Palette {
+    /// Color of every tile of a template, by index within the template.
+    templates: HashMap<u16, Vec<Rgba<u8>>>,
+    clear: Rgba<u8>
+}
+
+impl Palette {
+    /// A rough palette for the common tilesets: clear terrain and the water templates shared by
+    /// the Red Alert and Tiberian Dawn tilesets. Use `from_tileset_yaml` for the real colors.
+    pub fn builtin(tileset: &str) -> Self {
+        let clear = match tileset.to_uppercase().as_str() {
+            "SNOW" | "WINTER" => Rgba([196, 196, 200, 255]),
+            "DESERT" | "ARRAKIS" => Rgba([176, 148, 96, 255]),
+            "INTERIOR" => Rgba([96, 96, 96, 255]),
+            "JUNGLE" => Rgba([44, 84, 28, 255]),
+            _ => Rgba([40, 68, 40, 255])
+        };
+        let water = Rgba([32, 64, 132, 255]);
+        let mut templates = HashMap::new();
+        templates.insert(1, vec![water]);
+        templates.insert(2, vec![water]);
+        Palette {
+            templates,
+            clear
+        }
+    }
+
+    /// Reads the terrain colors from a tileset definition of the mod, e.g. `mods/ra/tilesets/temperat.yaml`.
+    pub fn from_tileset_yaml(path: &str) -> Result<Self, Error> {
+        let yaml = fs::read_to_string(path)?;
+        let nodes = miniyaml::parse(&yaml).map_err(|e| Error::other(format!("Could not read {}: {}", path, e)))?;
+        let section = |key| nodes.iter().find(|node: &&MiniYamlNode| node.key == key).map_or(&[][..], |node| node.children.as_slice());
+
+        let mut terrain_colors: HashMap<String, Rgba<u8>> = HashMap::new();
+        for terrain in section("Terrain") {
+            if let (Some(terrain_type), Some(color)) = (terrain.value_of("Type"), terrain.value_of("Color").and_then(parse_color)) {
+                terrain_colors.insert(terrain_type.to_string(), color);
+            }
+        }
+        let mut template_tiles: HashMap<u16, Vec<(usize, String)>> = HashMap::new();
+        for template in section("Templates") {
+            let (id, tiles) = match (template.value_of("Id").and_then(|id| id.parse().ok()), template.child("Tiles")) {
+                (Some(id), Some(tiles)) => (id, tiles),
+                _ => continue
+            };
+            for tile in &tiles.children {
+                if let Ok(index) = tile.key.parse::<usize>() {
+                    template_tiles.entry(id).or_default().push((index, tile.value.clone()));
+                }
+            }
+        }
+
+        let builtin = Palette::builtin("");
+        let mut templates = HashMap::new();
+        for (id, tiles) in template_tiles {
+            let size = tiles.iter().map(|(index, _)| index + 1).max().unwrap_or(0);
+            let mut colors = vec![builtin.clear; size];
+            for (index, terrain) in tiles {
+                colors[index] = terrain_colors.get(&terrain).copied().unwrap_or(builtin.clear);
+            }
+            templates.insert(id, colors);
+        }
+        Ok(Palette {
+            templates,
+            clear: terrain_colors.get("Clear").copied().unwrap_or(builtin.clear)
+        })
+    }
+
+    fn tile_color(&self, (template, index): (u16, u8)) -> Rgba<u8> {
+        match self.templates.get(&template) {
+            Some(colors) if colors.len() == 1 => colors[0],
+            Some(colors) => colors.get(index as usize).copied().unwrap_or(self.clear),
+            None => self.clear
+        }
+    }
+}
+
+/// Resource colors for the usual ore/tiberium (1) and gems/blue tiberium (2) resource types.
+fn resource_color(resource_type: u8) -> Option<Rgba<u8>> {
+    match resource_type {
+        0 => None,
+        1 => Some(Rgba([148, 128, 64, 255])),
+        2 => Some(Rgba([132, 60, 148, 255])),
+        _ => Some(Rgba([160, 160, 96, 255]))
+    }
+}
+
+/// Paints every cell within the bounds with the color of its terrain, or of its resources.
+/// Like OpenRA's previews, isometric maps get rows of half a cell high with odd rows shifted half a cell.
+pub fn render(layers: &TileLayers, palette: &Palette, bounds: Bounds, grid: MapGridType, pixels_per_cell: u32) -> DynamicImage {
+    let (row_height, shift) = match grid {
+        MapGridType::Rectangular => (pixels_per_cell, 0),
+        MapGridType::RectangularIsometric => ((pixels_per_cell / 2).max(1), pixels_per_cell / 2)
+    };
+    let mut image = RgbaImage::new(bounds.width as u32 * pixels_per_cell + shift, bounds.height as u32 * row_height);
+    for y in 0..bounds.height {
+        for x in 0..bounds.width {
+            let (u, v) = (x + bounds.x, y + bounds.y);
+            if u < 0 || v < 0 || u >= layers.width as i32 || v >= layers.height as i32 {
+                continue;
+            }
+            let (u, v) = (u as u16, v as u16);
+            let (resource_type, _density) = layers.resource(u, v);
+            let color = resource_color(resource_type).unwrap_or_else(|| palette.tile_color(layers.tile(u, v)));
+            let left = x as u32 * pixels_per_cell + if v % 2 == 1 { shift } else { 0 };
+            for dy in 0..row_height {
+                for dx in 0..pixels_per_cell {
+                    image.put_pixel(left + dx, y as u32 * row_height + dy, color);
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Tileset colors are written as `RRGGBB`, `RRGGBBAA`, `R,G,B` or `A,R,G,B`.
+pub fn parse_color(value: &str) -> Option<Rgba<u8>> {
+    if value.contains(',') {
+        let parts: Vec<u8> = value.split(',').filter_map(|p| p.trim().parse().ok()).collect();
+        return match parts.as_slice() {
+            [r, g, b] => Some(Rgba([*r, *g, *b, 255])),
+            [a, r, g, b] => Some(Rgba([*r, *g, *b, *a])),
+            _ => None
+        };
+    }
+    let rgb = u32::from_str_radix(value, 16).ok()?;
+    match value.len() {
+        6 => Some(Rgba([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255])),
+        8 => Some(Rgba([(rgb >> 24) as u8, (rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])),
+        _ => None
+    }
+}
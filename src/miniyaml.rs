@@ -0,0 +1,101 @@
+use crate::error::YamlError;
+
+/// A node of a MiniYaml document, the format OpenRA writes replay metadata, maps and mod rules in:
+/// `Key: value` lines, with the children of a node indented one tab (or four spaces) deeper.
+/// Keys of nodes that occur more than once carry a suffix, e.g. `Player@0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MiniYamlNode {
+    pub key: String,
+    /// Everything after the colon, trimmed. Empty for nodes that only have children.
+    pub value: String,
+    pub children: Vec<MiniYamlNode>,
+    /// Where the node is in the document, counted from 1.
+    pub line: usize
+}
+
+impl MiniYamlNode {
+    /// The key without its `@suffix`.
+    pub fn name(&self) -> &str {
+        self.key.split('@').next().unwrap_or_default()
+    }
+
+    /// The first child with this key.
+    pub fn child(&self, key: &str) -> Option<&MiniYamlNode> {
+        self.children.iter().find(|child| child.key == key)
+    }
+
+    /// The value of the first child with this key.
+    pub fn value_of(&self, key: &str) -> Option<&str> {
+        self.child(key).map(|child| child.value.as_str())
+    }
+}
+
+/// Parses a MiniYaml document into its top level nodes. Comments (`#`, unless escaped as `\#`) and empty lines are skipped.
+///
+/// ```
+/// let nodes = openra_heatmap::miniyaml::parse("Root:\n\tMod: ra\nPlayer@0:\n\tName: Some One\n").unwrap();
+/// let players: Vec<&str> = nodes.iter()
+///     .filter(|node| node.name() == "Player")
+///     .filter_map(|player| player.value_of("Name"))
+///     .collect();
+/// assert_eq!(players, ["Some One"]);
+/// ```
+pub fn parse(text: &str) -> Result<Vec<MiniYamlNode>, YamlError> {
+    let mut lines = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_start_matches('\u{feff}');
+        let content = strip_comment(line);
+        if content.trim().is_empty() {
+            continue;
+        }
+        let mut level = 0;
+        let mut spaces = 0;
+        for c in content.chars() {
+            match c {
+                '\t' => level += 1,
+                ' ' => spaces += 1,
+                _ => break
+            }
+        }
+        let content = content.trim().replace("\\#", "#");
+        let (key, value) = match content.split_once(':') {
+            Some((key, value)) => (key.trim().to_string(), value.trim().to_string()),
+            None => (content, String::new())
+        };
+        lines.push((level + spaces / 4, MiniYamlNode { key, value, children: Vec::new(), line: index + 1 }));
+    }
+    let mut pos = 0;
+    build(&lines, &mut pos, 0)
+}
+
+/// Collects the nodes at `level` from `pos` on, with their children, up to the first node at a lower level.
+fn build(lines: &[(usize, MiniYamlNode)], pos: &mut usize, level: usize) -> Result<Vec<MiniYamlNode>, YamlError> {
+    let mut nodes = Vec::new();
+    while *pos < lines.len() {
+        let (node_level, node) = &lines[*pos];
+        if *node_level < level {
+            break;
+        }
+        if *node_level > level {
+            return Err(YamlError::BadIndentation { line: node.line });
+        }
+        let mut node = node.clone();
+        *pos += 1;
+        node.children = build(lines, pos, level + 1)?;
+        nodes.push(node);
+    }
+    Ok(nodes)
+}
+
+/// The line up to its comment.
+fn strip_comment(line: &str) -> &str {
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '#' if !escaped => return &line[..i],
+            '\\' => escaped = !escaped,
+            _ => escaped = false
+        }
+    }
+    line
+}
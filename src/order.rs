@@ -0,0 +1,295 @@
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+use crate::error::ReplayError;
+use crate::map::MapGridType;
+use crate::protocol::OrderEncoding;
+use crate::reader::ReplayReader;
+
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum TargetType { Invalid, Actor, Terrain, FrozenActor }
+
+/// The first byte of every order. Lobby syncs, chat and other orders handled outside the game world are `Handshake` (immediate) orders.
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(u8)]
+pub enum OrderType {
+	/// The server acknowledging a client's orders, since release-20230225.
+	Ack = 0x10,
+	/// Latency measurement between server and clients, since release-20230225.
+	Ping = 0x20,
+    SyncHash = 0x65,
+	/// The server changing the game speed to keep up with lagging clients, since release-20230225.
+	TickScale = 0x76,
+	Disconnect = 0xBF,
+	Handshake = 0xFE,
+	Fields = 0xFF
+}
+#[derive(Debug, Eq, PartialEq, TryFromPrimitive)]
+#[repr(i16)]
+pub enum OrderFields{
+		None = 0x0,
+		Target = 0x01,
+		ExtraActors = 0x02,
+		TargetString = 0x04,
+		Queued = 0x08,
+		ExtraLocation = 0x10,
+		ExtraData = 0x20,
+		TargetIsCell = 0x40,
+		Subject = 0x80,
+		Grouped = 0x100
+}
+
+/// A map cell, as packed by OpenRA's `CPos`: 12 bits x, 12 bits y and 8 bits map layer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct CPos {
+    pub x: i32,
+    pub y: i32,
+    pub layer: u8
+}
+
+impl CPos {
+    pub fn from_bits(bits: u32) -> Self {
+        let bits = bits as i32;
+        CPos {
+            x: bits >> 20,
+            y: (bits << 12) >> 20,
+            layer: bits as u8
+        }
+    }
+
+    /// The (u, v) position of the cell in the map's storage, which is what `MapSize` and `Bounds` refer to.
+    pub fn to_mpos(self, grid: MapGridType) -> (i32, i32) {
+        match grid {
+            MapGridType::Rectangular => (self.x, self.y),
+            // isometric cells are stored in rows of half a cell high, odd rows shifted half a cell to the right
+            MapGridType::RectangularIsometric => ((self.x - self.y) / 2, self.x + self.y)
+        }
+    }
+}
+
+/// A position in world units, as OpenRA's `WPos`. A cell is 1024 units wide.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct WPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32
+}
+
+impl WPos {
+    /// The cell containing this position, like OpenRA's `Map.CellContaining`.
+    pub fn to_cell(self, grid: MapGridType) -> CPos {
+        match grid {
+            MapGridType::Rectangular => CPos { x: self.x / 1024, y: self.y / 1024, layer: 0 },
+            // undoes the rotation of isometric cells, whose diagonal is 1448 units,
+            // rounding towards the cell whose center is nearest
+            MapGridType::RectangularIsometric => CPos {
+                x: (self.y + self.x - 724) / 1448,
+                y: (self.y - self.x + if self.y > self.x { 724 } else { -724 }) / 1448,
+                layer: 0
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Invalid,
+    Actor(u32),
+    FrozenActor { player_actor_id: u32, frozen_actor_id: u32 },
+    /// A terrain cell, when the order has `TargetIsCell` set.
+    Cell { cell: CPos, subcell: u8 },
+    /// A terrain position in world units, used by e.g. attack ground orders and support powers.
+    Position(WPos)
+}
+
+/// One order issued by a client, as recorded in the replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub frame: i32,
+    pub client: i32,
+    pub order: String,
+    /// Immediate orders (chat, lobby sync, handshake, ...) only carry the order string and `target_string`.
+    pub immediate: bool,
+    pub subject: Option<u32>,
+    pub target: Target,
+    pub target_string: Option<String>,
+    pub extra_actors: Vec<u32>,
+    pub extra_location: Option<CPos>,
+    pub extra_data: Option<u32>,
+    pub queued: bool,
+    pub grouped: Option<Vec<u32>>
+}
+
+impl Order {
+    fn new(frame: i32, client: i32, order: String) -> Self {
+        Order {
+            frame,
+            client,
+            order,
+            immediate: false,
+            subject: None,
+            target: Target::Invalid,
+            target_string: None,
+            extra_actors: Vec::new(),
+            extra_location: None,
+            extra_data: None,
+            queued: false,
+            grouped: None
+        }
+    }
+
+    /// The cell targeted by this order, if it targets a terrain cell.
+    pub fn target_cell(&self) -> Option<CPos> {
+        match self.target {
+            Target::Cell { cell, .. } => Some(cell),
+            _ => None
+        }
+    }
+
+    /// The cell targeted by this order, if it targets a terrain cell or a position on the terrain.
+    pub fn target_location(&self, grid: MapGridType) -> Option<CPos> {
+        match self.target {
+            Target::Cell { cell, .. } => Some(cell),
+            Target::Position(pos) => Some(pos.to_cell(grid)),
+            _ => None
+        }
+    }
+}
+
+/// Reads a single order of the given type from the packet at the reader's position.
+/// Returns `None` for order types that carry no order.
+pub(crate) fn read_order(reader: &mut ReplayReader, ordertype: OrderType, encoding: OrderEncoding, client: i32, frame: i32) -> Result<Option<Order>, ReplayError> {
+    match ordertype {
+        OrderType::Handshake => {
+            let name = reader.read_string()?;
+            let mut order = Order::new(frame, client, name);
+            order.immediate = true;
+            order.target_string = Some(reader.read_string()?);
+            Ok(Some(order))
+        },
+        OrderType::Fields => {
+            let mut order = Order::new(frame, client, reader.read_string()?);
+
+            let flags = if encoding.short_flags {
+                reader.read_i16()?
+            } else {
+                reader.read_u8()? as i16
+            };
+
+            if flags & OrderFields::Subject as i16 > 0 {
+                order.subject = Some(reader.read_u32()?);
+            }
+            if flags & OrderFields::Target as i16 > 0 {
+                let offset = reader.pos();
+                let target_type_byte = reader.read_u8()?;
+                let target_type = TargetType::try_from(target_type_byte)
+                    .map_err(|_| ReplayError::UnknownTargetType { offset, byte: target_type_byte })?;
+                order.target = match target_type {
+                    TargetType::Actor => Target::Actor(reader.read_u32()?),
+                    TargetType::FrozenActor => Target::FrozenActor {
+                        player_actor_id: reader.read_u32()?,
+                        frozen_actor_id: reader.read_u32()?
+                    },
+                    TargetType::Terrain => {
+                        if flags & OrderFields::TargetIsCell as i16 > 0 {
+                            Target::Cell {
+                                cell: CPos::from_bits(reader.read_u32()?),
+                                subcell: reader.read_u8()?
+                            }
+                        } else {
+                            Target::Position(WPos {
+                                x: reader.read_i32()?,
+                                y: reader.read_i32()?,
+                                z: reader.read_i32()?
+                            })
+                        }
+                    },
+                    TargetType::Invalid => Target::Invalid
+                };
+            }
+            if flags & OrderFields::TargetString as i16 > 0 {
+                order.target_string = Some(reader.read_string()?);
+            }
+            if flags & OrderFields::ExtraActors as i16 > 0 {
+                let count = reader.read_u32()?;
+                for _ in 0..count {
+                    order.extra_actors.push(reader.read_u32()?);
+                }
+            }
+            if flags & OrderFields::ExtraLocation as i16 > 0 {
+                order.extra_location = Some(CPos::from_bits(reader.read_u32()?));
+            }
+            if flags & OrderFields::ExtraData as i16 > 0 {
+                order.extra_data = Some(reader.read_u32()?);
+            }
+            order.queued = flags & OrderFields::Queued as i16 > 0;
+            if flags & OrderFields::Grouped as i16 > 0 {
+                let count = reader.read_i32()?;
+                let mut grouped = Vec::new();
+                for _ in 0..count {
+                    grouped.push(reader.read_u32()?);
+                }
+                order.grouped = Some(grouped);
+            }
+            Ok(Some(order))
+        },
+        _ => Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// OpenRA's `Map.CenterOfCell` for isometric maps, on the ground.
+    fn center_of_isometric_cell(x: i32, y: i32) -> WPos {
+        WPos { x: 724 * (x - y + 1), y: 724 * (x + y + 1), z: 0 }
+    }
+
+    #[test]
+    fn isometric_cell_containing_its_center() {
+        assert_eq!(WPos { x: 7964, y: 7964, z: 0 }.to_cell(MapGridType::RectangularIsometric), CPos { x: 10, y: 0, layer: 0 });
+        for x in 0..40 {
+            for y in -20..20 {
+                let center = center_of_isometric_cell(x, y);
+                assert_eq!(center.to_cell(MapGridType::RectangularIsometric), CPos { x, y, layer: 0 }, "cell {},{}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn rectangular_cell_containing_its_center() {
+        let center = WPos { x: 10 * 1024 + 512, y: 3 * 1024 + 512, z: 0 };
+        assert_eq!(center.to_cell(MapGridType::Rectangular), CPos { x: 10, y: 3, layer: 0 });
+    }
+
+    const BYTE_FLAGS: OrderEncoding = OrderEncoding { short_flags: false, ticks_per_net_frame: 3 };
+
+    #[test]
+    fn reads_order_targeting_cell() {
+        let bytes = [&[0x04][..], b"Move", &[0x41, 0x02], &0x00A0_0C00u32.to_le_bytes(), &[0x00]].concat();
+        let order = read_order(&mut ReplayReader::new(&bytes), OrderType::Fields, BYTE_FLAGS, 1, 7).unwrap().unwrap();
+        assert_eq!((order.order.as_str(), order.client, order.frame), ("Move", 1, 7));
+        assert_eq!(order.target_cell(), Some(CPos { x: 10, y: 12, layer: 0 }));
+    }
+
+    #[test]
+    fn unknown_target_type() {
+        // "Move" with a target of type 9
+        let bytes = [&[0x04][..], b"Move", &[0x01, 0x09]].concat();
+        match read_order(&mut ReplayReader::new(&bytes), OrderType::Fields, BYTE_FLAGS, 0, 0) {
+            Err(ReplayError::UnknownTargetType { offset: 6, byte: 9 }) => {},
+            other => panic!("expected UnknownTargetType at 6, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn order_cut_off_in_its_target_is_truncated() {
+        // "Move" targeting an actor, of whose id only two bytes are left
+        let bytes = [&[0x04][..], b"Move", &[0x01, 0x01, 0x2A, 0x00]].concat();
+        match read_order(&mut ReplayReader::new(&bytes), OrderType::Fields, BYTE_FLAGS, 0, 0) {
+            Err(ReplayError::Truncated { offset: 7, wanted: 4 }) => {},
+            other => panic!("expected Truncated at 7, got {:?}", other)
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::convert::TryFrom;
+use crate::error::ReplayError;
+use crate::order::OrderType;
+use crate::reader::ReplayReader;
+use crate::replay::ReadMode;
+
+/// A packet of the order stream: `[client][length][frame][orders]`, as the server relayed it.
+pub(crate) struct Packet {
+    pub client: i32,
+    /// Where the packet ends, just after its last order.
+    pub end: usize,
+    /// The type of its first order, which tells what kind of packet it is. None for packets without orders.
+    pub kind: Option<Result<OrderType, u8>>
+}
+
+/// Walks the packets of the order stream, up to the metadata trailer.
+pub(crate) struct Packets<'a> {
+    pub reader: ReplayReader<'a>,
+    mode: ReadMode
+}
+
+impl<'a> Packets<'a> {
+    pub fn new(data: &'a [u8], mode: ReadMode) -> Self {
+        Packets {
+            reader: ReplayReader::new(data),
+            mode
+        }
+    }
+
+    /// Moves to the next packet, leaving the reader at its frame. Returns None at the end of the order stream.
+    pub fn next_packet(&mut self) -> Result<Option<Packet>, ReplayError> {
+        if self.mode == ReadMode::Salvage && self.reader.pos() == self.reader.len() {
+            // the game ended before the metadata trailer was written
+            return Ok(None);
+        }
+        let client = self.reader.read_i32()?;
+        if client == -1 {
+            return Ok(None);
+        }
+
+        let offset = self.reader.pos();
+        let packet_len = self.reader.read_i32()?;
+        let end = usize::try_from(packet_len).ok()
+            .and_then(|len| self.reader.pos().checked_add(len))
+            .ok_or(ReplayError::OutOfRange { offset, len: self.reader.len() })?;
+        if end > self.reader.len() {
+            return Err(ReplayError::Truncated { offset: self.reader.len(), wanted: end - self.reader.len() });
+        }
+        let kind = if packet_len >= 5 {
+            let byte = self.reader.at_relative_offset(4)?;
+            Some(OrderType::try_from(byte).map_err(|_| byte))
+        } else {
+            None
+        };
+        Ok(Some(Packet { client, end, kind }))
+    }
+
+    pub fn skip(&mut self, packet: &Packet) -> Result<(), ReplayError> {
+        self.reader.set_pos(packet.end)
+    }
+}
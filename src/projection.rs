@@ -0,0 +1,77 @@
+use openra_heatmap::{Bounds, CPos, MapGridType};
+
+/// Maps cells onto an image of the map, such as a screenshot or preview.
+/// Like OpenRA's own previews, the image is expected to show only the map's `Bounds`.
+///
+/// All positions go through the "grid": the map position (u, v) relative to the top left of the bounds.
+/// On isometric maps every grid row is half a cell high and odd rows are shifted half a cell to the right.
+pub struct CellToPixel {
+    bounds: Bounds,
+    grid: MapGridType,
+    /// Pixels per grid column and row.
+    scale_x: f32,
+    scale_y: f32
+}
+
+impl CellToPixel {
+    pub fn new(bounds: Bounds, grid: MapGridType, (image_width, image_height): (u32, u32)) -> Self {
+        let columns = match grid {
+            MapGridType::Rectangular => bounds.width as f32,
+            // in half cells: every row is a cell wider than the next half shifted one
+            MapGridType::RectangularIsometric => bounds.width as f32 + 0.5
+        };
+        CellToPixel {
+            bounds,
+            grid,
+            scale_x: image_width as f32 / columns,
+            scale_y: image_height as f32 / bounds.height as f32
+        }
+    }
+
+    /// Width and height of the grid, in cells.
+    pub fn grid_size(&self) -> (usize, usize) {
+        (self.bounds.width.max(1) as usize, self.bounds.height.max(1) as usize)
+    }
+
+    /// Width and height of a grid cell in pixels. On isometric maps a cell covers two grid rows.
+    pub fn cell_size(&self) -> (f32, f32) {
+        (self.scale_x, self.scale_y)
+    }
+
+    /// The grid position of a cell. May lie outside of the grid for cells outside the bounds.
+    pub fn to_grid(&self, cell: CPos) -> (i32, i32) {
+        let (u, v) = cell.to_mpos(self.grid);
+        (u - self.bounds.x, v - self.bounds.y)
+    }
+
+    /// The grid position of a cell, None for cells outside the bounds.
+    pub fn to_grid_within(&self, cell: CPos) -> Option<(i32, i32)> {
+        let (x, y) = self.to_grid(cell);
+        let (width, height) = self.grid_size();
+        if x < 0 || y < 0 || x >= width as i32 || y >= height as i32 { None } else { Some((x, y)) }
+    }
+
+    /// The pixel at the center of a cell. May lie outside of the image for cells outside the bounds.
+    pub fn to_pixel(&self, cell: CPos) -> (f32, f32) {
+        let (x, y) = self.to_grid(cell);
+        self.grid_to_pixel(x as f32, y as f32, y)
+    }
+
+    fn grid_to_pixel(&self, x: f32, y: f32, row: i32) -> (f32, f32) {
+        let shift = match self.grid {
+            MapGridType::Rectangular => 0.0,
+            MapGridType::RectangularIsometric => 0.5 * (row + self.bounds.y).rem_euclid(2) as f32
+        };
+        ((x + 0.5 + shift) * self.scale_x, (y + 0.5) * self.scale_y)
+    }
+
+    /// The (fractional) grid position shown at a pixel, with cell centers at whole numbers.
+    pub fn pixel_to_grid(&self, px: f32, py: f32) -> (f32, f32) {
+        let y = py / self.scale_y - 0.5;
+        let shift = match self.grid {
+            MapGridType::Rectangular => 0.0,
+            MapGridType::RectangularIsometric => 0.5 * (y.round() as i32 + self.bounds.y).rem_euclid(2) as f32
+        };
+        (px / self.scale_x - 0.5 - shift, y)
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+
+/// How a release encodes the orders in its replays.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct OrderEncoding {
+    /// The order flags are a short, which made room for `OrderFields::Grouped`, rather than a byte.
+    pub short_flags: bool,
+    /// Game ticks per frame of the order stream. Older releases only handled orders every few ticks,
+    /// the network code of release-20230225 handles them every tick.
+    pub ticks_per_net_frame: u64
+}
+
+const BYTE_FLAGS: OrderEncoding = OrderEncoding { short_flags: false, ticks_per_net_frame: 3 };
+const SHORT_FLAGS: OrderEncoding = OrderEncoding { short_flags: true, ticks_per_net_frame: 3 };
+const EVERY_TICK: OrderEncoding = OrderEncoding { short_flags: true, ticks_per_net_frame: 1 };
+
+/// Every release since 2018 by date, with how it encodes orders. Playtests encode orders like the release they led up to.
+const RELEASES: &[(u32, OrderEncoding)] = &[
+    (20180218, BYTE_FLAGS),
+    (20180307, BYTE_FLAGS),
+    (20180923, BYTE_FLAGS),
+    (20181215, BYTE_FLAGS),
+    (20190314, BYTE_FLAGS),
+    (20191117, BYTE_FLAGS),
+    (20200202, BYTE_FLAGS),
+    (20200503, BYTE_FLAGS),
+    (20210321, SHORT_FLAGS),
+    (20230225, EVERY_TICK),
+    (20231010, EVERY_TICK),
+    (20250330, EVERY_TICK)
+];
+
+/// The engine version a replay was recorded with, which decides how its orders are encoded.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ProtocolVersion {
+    /// `release-yyyyMMdd`, by its date.
+    Release(u32),
+    /// `playtest-yyyyMMdd`, by its date.
+    Playtest(u32),
+    /// `{{DEV_VERSION}}`, a build from source, assumed to be as recent as the newest release.
+    Development,
+    /// A version that can't be made sense of, e.g. the name of a git branch. Empty when the replay doesn't tell.
+    Unknown(String)
+}
+
+impl ProtocolVersion {
+    pub fn parse(version: &str) -> ProtocolVersion {
+        let date = |date: &str| date.get(..8).and_then(|date| date.parse().ok());
+        if version == "{{DEV_VERSION}}" {
+            return ProtocolVersion::Development;
+        }
+        let parsed = match version.split_once('-') {
+            Some(("release", rest)) => date(rest).map(ProtocolVersion::Release),
+            Some(("playtest", rest)) => date(rest).map(ProtocolVersion::Playtest),
+            _ => None
+        };
+        parsed.unwrap_or_else(|| ProtocolVersion::Unknown(version.to_string()))
+    }
+
+    /// How the orders are encoded, from the release at or after this version's date.
+    /// Versions newer than every known release are taken to encode orders like the newest one.
+    /// None for unknown versions.
+    pub fn encoding(&self) -> Option<OrderEncoding> {
+        let newest = RELEASES.last().map(|&(_, encoding)| encoding);
+        match *self {
+            ProtocolVersion::Release(date) | ProtocolVersion::Playtest(date) => {
+                RELEASES.iter().find(|&&(release, _)| release >= date).map(|&(_, encoding)| encoding).or(newest)
+            },
+            ProtocolVersion::Development => newest,
+            ProtocolVersion::Unknown(_) => None
+        }
+    }
+
+    /// Why the orders of this version may not be read correctly, None for the releases in the table and their playtests.
+    pub fn warning(&self) -> Option<String> {
+        let (first, last) = (RELEASES[0].0, RELEASES[RELEASES.len() - 1].0);
+        match *self {
+            ProtocolVersion::Release(date) | ProtocolVersion::Playtest(date) if date > last => {
+                Some(format!("{} is newer than release-{}, the newest release known, its orders are read like those of that release", self, last))
+            },
+            ProtocolVersion::Release(date) | ProtocolVersion::Playtest(date) if date < first => {
+                Some(format!("{} is older than release-{}, the oldest release supported, its orders may not be read correctly", self, first))
+            },
+            ProtocolVersion::Release(date) if !RELEASES.iter().any(|&(release, _)| release == date) => {
+                Some(format!("{} is not a known release, its orders are read like those of the release after it", self))
+            },
+            ProtocolVersion::Unknown(ref version) if version.is_empty() => {
+                Some("the replay does not tell its version, the encoding of its orders is guessed".to_string())
+            },
+            ProtocolVersion::Unknown(ref version) => {
+                Some(format!("unknown version '{}', the encoding of its orders is guessed", version))
+            },
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolVersion::Release(date) => write!(f, "release-{}", date),
+            ProtocolVersion::Playtest(date) => write!(f, "playtest-{}", date),
+            ProtocolVersion::Development => write!(f, "a development build"),
+            ProtocolVersion::Unknown(version) => write!(f, "{}", version)
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use byteorder::{ByteOrder, LittleEndian};
+use encoding::all::UTF_8;
+use encoding::{Encoding, DecoderTrap};
+use crate::error::ReplayError;
+
+// Given a stream of bytes, extract first number
+pub fn decode_slice(bytes: &[u8], index: &mut usize) -> Result<u32, ReplayError> {
+    // Read out an Int32 7 bits at a time.  The high bit
+    // of the byte when on means to continue reading more bytes.
+    let start = *index;
+    let mut count : u32 = 0;
+    let mut shift : u32 = 0;
+    let mut b:u16;
+    loop {
+        if shift > 28 {
+            return Err(ReplayError::BadVarint { offset: start });
+        }
+        b = *bytes.get(*index).ok_or(ReplayError::Truncated { offset: *index, wanted: 1 })? as u16;
+        let tmp = b & 0x7F;
+        let tmp2 = (tmp as u32) << shift;
+        count |= tmp2;
+        shift += 7;
+        *index += 1;
+
+        if (b & 0x80) == 0 {
+            return Ok(count)
+        }
+    }
+}
+
+/// Cursor over the raw bytes of a replay, reading values the way OpenRA's `BinaryWriter` wrote them.
+pub struct ReplayReader<'a> {
+    pos: usize,
+    map: &'a [u8]
+}
+
+impl<'a> ReplayReader<'a> {
+    pub fn new(map: &'a [u8]) -> Self {
+        ReplayReader {
+            pos: 0,
+            map
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Takes the next `count` bytes, failing if the replay ends before that.
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplayError> {
+        let end = self.pos.checked_add(count)
+            .filter(|&end| end <= self.map.len())
+            .ok_or(ReplayError::Truncated { offset: self.pos, wanted: count })?;
+        let bytes = &self.map[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, ReplayError> {
+        let strlength = decode_slice(self.map, &mut self.pos)? as usize;
+        let offset = self.pos;
+        let bytes = self.take(strlength)?;
+        UTF_8.decode(bytes, DecoderTrap::Replace).map_err(|_| ReplayError::InvalidUtf8 { offset })
+    }
+
+    pub fn read_string_with_length(&mut self, strlength: usize) -> Result<String, ReplayError> {
+        let offset = self.pos;
+        let bytes = self.take(strlength)?;
+        UTF_8.decode(bytes, DecoderTrap::Strict).map_err(|_| ReplayError::InvalidUtf8 { offset })
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, ReplayError> {
+        Ok(LittleEndian::read_i32(self.take(4)?))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, ReplayError> {
+        Ok(LittleEndian::read_u32(self.take(4)?))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, ReplayError> {
+        Ok(LittleEndian::read_u64(self.take(8)?))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, ReplayError> {
+        Ok(LittleEndian::read_i16(self.take(2)?))
+    }
+
+    pub fn at_relative_offset(&self, offset: usize) -> Result<u8, ReplayError> {
+        let offset = self.pos + offset;
+        self.map.get(offset).copied().ok_or(ReplayError::OutOfRange { offset, len: self.map.len() })
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, ReplayError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn set_pos(&mut self, pos: usize) -> Result<(), ReplayError> {
+        if pos > self.map.len() {
+            return Err(ReplayError::OutOfRange { offset: pos, len: self.map.len() });
+        }
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_multi_byte_varint() {
+        let mut index = 1;
+        assert_eq!(decode_slice(&[0x00, 0xAC, 0x02, 0x07], &mut index).unwrap(), 300);
+        assert_eq!(index, 3);
+    }
+
+    #[test]
+    fn varint_cut_off_is_truncated() {
+        let mut index = 1;
+        match decode_slice(&[0x00, 0x80, 0x80], &mut index) {
+            Err(ReplayError::Truncated { offset: 3, wanted: 1 }) => {},
+            other => panic!("expected Truncated at 3, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn varint_longer_than_five_bytes_is_bad() {
+        let mut index = 2;
+        match decode_slice(&[0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01], &mut index) {
+            Err(ReplayError::BadVarint { offset: 2 }) => {},
+            other => panic!("expected BadVarint at 2, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn reading_past_the_end_is_truncated() {
+        let mut reader = ReplayReader::new(&[1, 0, 0, 0, 2, 0]);
+        assert_eq!(reader.read_i32().unwrap(), 1);
+        match reader.read_i32() {
+            Err(ReplayError::Truncated { offset: 4, wanted: 4 }) => {},
+            other => panic!("expected Truncated at 4, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn string_longer_than_the_replay_is_truncated() {
+        let mut reader = ReplayReader::new(&[0x05, b'a', b'b']);
+        match reader.read_string() {
+            Err(ReplayError::Truncated { offset: 1, wanted: 5 }) => {},
+            other => panic!("expected Truncated at 1, got {:?}", other)
+        }
+    }
+}
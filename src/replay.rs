@@ -0,0 +1,508 @@
+use memmap::Mmap;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::ops::Deref;
+use std::path::Path;
+use std::time::Duration;
+use crate::error::ReplayError;
+use crate::header::ReplayHeader;
+use crate::lobby::Lobby;
+use crate::metadata::{Outcome, ReplayMetadata, GRAY};
+pub use crate::metadata::Player;
+use crate::order::{read_order, Order, OrderType};
+use crate::packet::Packets;
+use crate::protocol::{OrderEncoding, ProtocolVersion};
+use crate::reader::ReplayReader;
+use crate::sync::SyncHashes;
+
+/// Milliseconds per tick at the default game speed.
+const DEFAULT_TIMESTEP: u64 = 40;
+
+pub struct GameInformation {
+    pub version: String,
+    /// The mod the game was played with, e.g. `ra`, `cnc`, `d2k` or `ts`. Empty when unknown.
+    pub mod_id: String,
+    pub map_uid : String,
+    pub players: HashMap<i32, Player>
+}
+
+impl GameInformation {
+    /// What the handshake and lobby tell, for replays without metadata trailer. The players are the clients of the lobby
+    /// that took a slot, as the game started; without lobby only the clients that introduced themselves in the handshake.
+    fn from_header(header: &ReplayHeader, lobby: Option<&Lobby>) -> Self {
+        let fingerprint = |client_id| header.clients.get(&client_id).and_then(|client| client.fingerprint.clone());
+        let players: HashMap<i32, Player> = match lobby {
+            Some(lobby) => lobby.clients.iter().filter(|client| !client.is_spectator()).map(|client| (client.index, Player {
+                client_id: client.index,
+                name: client.name.clone(),
+                color: client.color.unwrap_or(GRAY),
+                team: client.team,
+                faction_name: String::new(),
+                faction_id: client.faction.clone(),
+                spawn_point: client.spawn_point,
+                outcome: Outcome::Undefined,
+                is_bot: client.bot.is_some(),
+                bot_type: client.bot.clone(),
+                fingerprint: fingerprint(client.index)
+            })).collect(),
+            None => header.clients.iter().map(|(&client_id, client)| (client_id, Player {
+                client_id,
+                name: client.name.clone(),
+                color: client.color.unwrap_or(GRAY),
+                team: 0,
+                faction_name: String::new(),
+                faction_id: String::new(),
+                spawn_point: 0,
+                outcome: Outcome::Undefined,
+                is_bot: false,
+                bot_type: None,
+                fingerprint: client.fingerprint.clone()
+            })).collect()
+        };
+        GameInformation {
+            version: header.version.clone().unwrap_or_default(),
+            mod_id: header.mod_id.clone().unwrap_or_default(),
+            map_uid: header.map_uid.clone().unwrap_or_default(),
+            players
+        }
+    }
+
+    fn from_metadata(metadata: &ReplayMetadata) -> Self {
+        GameInformation {
+            version: metadata.version.clone(),
+            mod_id: metadata.mod_id.clone(),
+            map_uid: metadata.map_uid.clone(),
+            players: metadata.players.clone()
+        }
+    }
+
+    /// The engine version, which decides how the orders are encoded.
+    pub fn protocol_version(&self) -> ProtocolVersion {
+        ProtocolVersion::parse(&self.version)
+    }
+}
+
+enum ReplayData {
+    Mapped(Mmap),
+    Owned(Vec<u8>)
+}
+
+impl Deref for ReplayData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            ReplayData::Mapped(map) => map,
+            ReplayData::Owned(bytes) => bytes
+        }
+    }
+}
+
+/// How strictly a replay is read.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReadMode {
+    /// Any malformed data is an error.
+    Strict,
+    /// For replays of crashed or still running games: a missing metadata trailer is tolerated,
+    /// and reading orders stops cleanly at the first truncated or unreadable packet.
+    Salvage
+}
+
+/// An OpenRA replay: the game information from its metadata trailer plus the recorded orders.
+pub struct Replay {
+    data: ReplayData,
+    info: GameInformation,
+    metadata: Option<ReplayMetadata>,
+    header: ReplayHeader,
+    protocol_version: ProtocolVersion,
+    encoding: OrderEncoding,
+    mode: ReadMode
+}
+
+impl Replay {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        Replay::open_with_mode(path, ReadMode::Strict)
+    }
+
+    pub fn open_with_mode<P: AsRef<Path>>(path: P, mode: ReadMode) -> Result<Replay, ReplayError> {
+        let file = File::open(path)?;
+        let map = unsafe { Mmap::map(&file)? };
+        Replay::new(ReplayData::Mapped(map), mode)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Replay, ReplayError> {
+        Replay::from_bytes_with_mode(bytes, ReadMode::Strict)
+    }
+
+    pub fn from_bytes_with_mode(bytes: Vec<u8>, mode: ReadMode) -> Result<Replay, ReplayError> {
+        Replay::new(ReplayData::Owned(bytes), mode)
+    }
+
+    fn new(data: ReplayData, mode: ReadMode) -> Result<Replay, ReplayError> {
+        // handshake and lobby orders are immediate orders, which are encoded the same whatever the width of the flags
+        let any_encoding = OrderEncoding { short_flags: false, ticks_per_net_frame: 1 };
+        let start = || Orders::new(&data, any_encoding, ReadMode::Strict).map_while(Result::ok);
+        let header = ReplayHeader::read(start());
+        let (info, metadata) = match read_metadata(&mut ReplayReader::new(&data)) {
+            Ok(metadata) => (GameInformation::from_metadata(&metadata), Some(metadata)),
+            Err(_) if mode == ReadMode::Salvage => {
+                let lobby = Lobby::snapshots(start().take_while(|order| order.immediate)).pop();
+                (GameInformation::from_header(&header, lobby.as_ref()), None)
+            },
+            Err(e) => return Err(e)
+        };
+        let protocol_version = info.protocol_version();
+        let encoding = protocol_version.encoding().unwrap_or_else(|| {
+            // Without a version we know we cannot tell how wide the order flags are,
+            // so go with whichever width makes more of the replay readable.
+            let readable = |short_flags| Orders::new(&data, OrderEncoding { short_flags, ticks_per_net_frame: 1 }, ReadMode::Strict)
+                .take_while(|order| order.is_ok())
+                .count();
+            let short_flags = readable(true) > readable(false);
+            // the newest releases, like development builds, handle orders every tick
+            OrderEncoding { short_flags, ticks_per_net_frame: if short_flags { 1 } else { 3 } }
+        });
+        Ok(Replay {
+            data,
+            info,
+            metadata,
+            header,
+            protocol_version,
+            encoding,
+            mode
+        })
+    }
+
+    /// The game information. For replays without metadata trailer (see `has_metadata`), only what the handshake tells.
+    pub fn info(&self) -> &GameInformation {
+        &self.info
+    }
+
+    pub fn into_info(self) -> GameInformation {
+        self.info
+    }
+
+    /// False for salvaged replays whose metadata trailer was never written.
+    pub fn has_metadata(&self) -> bool {
+        self.metadata.is_some()
+    }
+
+    /// What the handshake and lobby at the start of the replay tell, also for replays without metadata trailer.
+    pub fn header(&self) -> &ReplayHeader {
+        &self.header
+    }
+
+    /// The engine version the replay was recorded with. See `ProtocolVersion::warning` for versions whose orders may be misread.
+    pub fn protocol_version(&self) -> &ProtocolVersion {
+        &self.protocol_version
+    }
+
+    /// How the orders are encoded: as the protocol version tells, or as guessed from the orders for unknown versions.
+    pub fn encoding(&self) -> OrderEncoding {
+        self.encoding
+    }
+
+    /// Everything the metadata trailer tells about the game, None when the replay has none.
+    pub fn metadata(&self) -> Option<&ReplayMetadata> {
+        self.metadata.as_ref()
+    }
+
+    /// The lobby as the game started: its players, slots and settings. None when the replay has no lobby orders.
+    pub fn lobby(&self) -> Option<Lobby> {
+        // the lobby is synced to every client before the game starts
+        let lobby = self.orders()
+            .map_while(Result::ok)
+            .take_while(|order| order.immediate);
+        Lobby::snapshots(lobby).pop()
+    }
+
+    /// The lobby after every lobby order in the replay, including the ones synced during the game,
+    /// e.g. when a client dropped.
+    pub fn lobby_snapshots(&self) -> Vec<Lobby> {
+        Lobby::snapshots(self.orders().map_while(Result::ok))
+    }
+
+    /// How long a frame of the order stream took, from the game speed set in the lobby and the ticks per frame of the release.
+    /// Falls back to the default game speed when the lobby settings can't be found.
+    pub fn frame_duration(&self) -> Duration {
+        let timestep = self.lobby().and_then(|lobby| lobby.settings.tick_duration());
+        Duration::from_millis(timestep.unwrap_or(DEFAULT_TIMESTEP) * self.encoding.ticks_per_net_frame)
+    }
+
+    /// How long the game ran, up to the last frame a client reported its sync hash for.
+    /// None when the replay has no sync hashes.
+    pub fn game_length(&self) -> Option<Duration> {
+        let last_frame = self.sync_hashes().map_while(Result::ok).map(|sync_hash| sync_hash.frame).max()?;
+        Some(self.frame_duration() * u32::try_from(last_frame).unwrap_or(0))
+    }
+
+    /// Iterates over the sync hashes the clients reported, in the order they were recorded.
+    pub fn sync_hashes(&self) -> SyncHashes<'_> {
+        SyncHashes::new(&self.data, self.mode)
+    }
+
+    /// Iterates over all orders in the replay, in the order they were recorded.
+    /// The iteration ends after the first error; in salvage mode that error is not returned
+    /// but kept in `Orders::stats`.
+    pub fn orders(&self) -> Orders<'_> {
+        Orders::new(&self.data, self.encoding, self.mode)
+    }
+}
+
+/// How much of the order stream was read.
+#[derive(Debug, Default)]
+pub struct ReadStats {
+    pub frames: usize,
+    pub orders: usize,
+    pub last_frame: i32,
+    /// Packets of a kind that carries no orders we can read, e.g. from a newer release.
+    pub skipped_packets: usize,
+    /// Why reading stopped early, when salvaging a damaged replay.
+    pub error: Option<ReplayError>
+}
+
+pub struct Orders<'a> {
+    packets: Packets<'a>,
+    encoding: OrderEncoding,
+    mode: ReadMode,
+    packet_end: usize,
+    client: i32,
+    frame: i32,
+    stats: ReadStats,
+    done: bool
+}
+
+impl<'a> Orders<'a> {
+    fn new(data: &'a [u8], encoding: OrderEncoding, mode: ReadMode) -> Self {
+        Orders {
+            packets: Packets::new(data, mode),
+            encoding,
+            mode,
+            packet_end: 0,
+            client: 0,
+            frame: 0,
+            stats: ReadStats::default(),
+            done: false
+        }
+    }
+
+    pub fn stats(&self) -> &ReadStats {
+        &self.stats
+    }
+
+    pub fn into_stats(self) -> ReadStats {
+        self.stats
+    }
+
+    /// Moves to the next packet carrying orders. Returns false at the end of the order stream.
+    fn next_packet(&mut self) -> Result<bool, ReplayError> {
+        loop {
+            let packet = match self.packets.next_packet()? {
+                Some(packet) => packet,
+                None => return Ok(false)
+            };
+            match packet.kind {
+                Some(Ok(OrderType::Handshake)) | Some(Ok(OrderType::Fields)) | None => {},
+                Some(Ok(_)) => {
+                    // sync hashes, disconnects and network bookkeeping carry no orders
+                    self.packets.skip(&packet)?;
+                    continue;
+                },
+                Some(Err(_)) => {
+                    self.stats.skipped_packets += 1;
+                    self.packets.skip(&packet)?;
+                    continue;
+                }
+            }
+
+            self.client = packet.client;
+            self.packet_end = packet.end;
+            self.frame = self.packets.reader.read_i32()?;
+            if self.frame != self.stats.last_frame || self.stats.frames == 0 {
+                self.stats.frames += 1;
+                self.stats.last_frame = self.frame;
+            }
+            return Ok(true);
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<Order>, ReplayError> {
+        loop {
+            if self.packets.reader.pos() >= self.packet_end {
+                if !self.next_packet()? {
+                    return Ok(None);
+                }
+                continue;
+            }
+
+            // unlike unknown packets, an unknown order after the first one of a packet means the packet was misread
+            let offset = self.packets.reader.pos();
+            let ordertypebyte = self.packets.reader.read_u8()?;
+            let ordertype = OrderType::try_from(ordertypebyte)
+                .map_err(|_| ReplayError::UnknownOrderType { offset, byte: ordertypebyte })?;
+            match read_order(&mut self.packets.reader, ordertype, self.encoding, self.client, self.frame)? {
+                Some(order) => return Ok(Some(order)),
+                None => {
+                    // nothing else in this packet we know how to read
+                    self.packets.reader.set_pos(self.packet_end)?;
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Orders<'a> {
+    type Item = Result<Order, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_next() {
+            Ok(Some(order)) => {
+                self.stats.orders += 1;
+                Some(Ok(order))
+            },
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                if self.mode == ReadMode::Salvage {
+                    self.stats.error = Some(e);
+                    None
+                } else {
+                    Some(Err(e))
+                }
+            }
+        }
+    }
+}
+
+fn read_metadata(reader: &mut ReplayReader) -> Result<ReplayMetadata, ReplayError> {
+    fn expect_marker(reader: &mut ReplayReader, expected: i32) -> Result<(), ReplayError> {
+        let offset = reader.pos();
+        let found = reader.read_i32()?;
+        if found != expected {
+            return Err(ReplayError::BadMarker { offset, expected, found });
+        }
+        Ok(())
+    }
+
+    let total_len = reader.len();
+    if total_len < 8 {
+        return Err(ReplayError::Truncated { offset: total_len, wanted: 8 - total_len });
+    }
+    reader.set_pos(total_len - 8)?;
+    let metadata_len = reader.read_i32()? as usize;
+    if reader.read_i32()? != -2 {
+        return Err(ReplayError::NoMetadata);
+    }
+    let start = metadata_len.checked_add(8 + 8)
+        .and_then(|len| total_len.checked_sub(len))
+        .ok_or(ReplayError::OutOfRange { offset: total_len - 8, len: total_len })?;
+    reader.set_pos(start)?;
+    expect_marker(reader, -1)?;
+    let _metadata_version = reader.read_i32()?;
+    let strlen = reader.read_i32()? as usize;
+    /* this string is encoded differently than all other strings.. */
+    let metadata = reader.read_string_with_length(strlen)?;
+    reader.set_pos(0)?; //reset to beginning
+    ReplayMetadata::parse(&metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METADATA: &str = "Root:\n\tMod: ra\n\tVersion: release-20200503\n\tMapUid: abc123\n";
+
+    fn packet(client: i32, frame: i32, orders: &[u8]) -> Vec<u8> {
+        [&client.to_le_bytes()[..], &(orders.len() as i32 + 4).to_le_bytes(), &frame.to_le_bytes(), orders].concat()
+    }
+
+    /// A move order to cell (10, 12), with the flags of release-20200503.
+    fn move_order() -> Vec<u8> {
+        [&[0xFF, 0x04][..], b"Move", &[0x41, 0x02], &0x00A0_0C00u32.to_le_bytes(), &[0x00]].concat()
+    }
+
+    fn handshake() -> Vec<u8> {
+        let yaml = "Handshake:\n\tMod: ra\n\tVersion: release-20200503\n\tMap: abc123\n";
+        packet(0, 0, &[&[0xFE, 16][..], b"HandshakeRequest", &[yaml.len() as u8], yaml.as_bytes()].concat())
+    }
+
+    /// The handshake and a move order in each of the frames 1 to 3.
+    fn orders() -> Vec<u8> {
+        [handshake(), packet(0, 1, &move_order()), packet(1, 2, &move_order()), packet(0, 3, &move_order())].concat()
+    }
+
+    fn trailer() -> Vec<u8> {
+        let len = METADATA.len() as i32;
+        [&(-1i32).to_le_bytes()[..], &1i32.to_le_bytes(), &len.to_le_bytes(), METADATA.as_bytes(), &(len + 4).to_le_bytes(), &(-2i32).to_le_bytes()].concat()
+    }
+
+    #[test]
+    fn unknown_order_type_after_the_first_order() {
+        let bytes = [packet(0, 1, &[&move_order()[..], &[0x42]].concat()), trailer()].concat();
+        let replay = Replay::from_bytes(bytes).unwrap();
+        let mut orders = replay.orders();
+        assert_eq!(orders.next().unwrap().unwrap().order, "Move");
+        match orders.next() {
+            Some(Err(ReplayError::UnknownOrderType { offset: 25, byte: 0x42 })) => {},
+            other => panic!("expected UnknownOrderType at 25, got {:?}", other)
+        }
+        assert!(orders.next().is_none());
+    }
+
+    #[test]
+    fn salvages_replay_cut_mid_packet() {
+        let mut bytes = orders();
+        bytes.truncate(bytes.len() - 5);
+        let replay = Replay::from_bytes_with_mode(bytes.clone(), ReadMode::Salvage).unwrap();
+        assert!(!replay.has_metadata());
+        assert_eq!(replay.info().map_uid, "abc123");
+        let mut orders = replay.orders();
+        assert_eq!(orders.by_ref().filter(|order| !order.as_ref().unwrap().immediate).count(), 2);
+        let stats = orders.into_stats();
+        assert_eq!((stats.orders, stats.last_frame, stats.skipped_packets), (3, 2, 0));
+        match stats.error {
+            Some(ReplayError::Truncated { offset, wanted: 5 }) if offset == bytes.len() => {},
+            other => panic!("expected Truncated at the end, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn salvages_replay_without_metadata() {
+        let replay = Replay::from_bytes_with_mode(orders(), ReadMode::Salvage).unwrap();
+        assert!(!replay.has_metadata());
+        assert_eq!(replay.protocol_version(), &ProtocolVersion::Release(20200503));
+        let mut orders = replay.orders();
+        assert!(orders.by_ref().all(|order| order.is_ok()));
+        let stats = orders.into_stats();
+        assert_eq!((stats.orders, stats.last_frame, stats.skipped_packets), (4, 3, 0));
+        assert!(stats.error.is_none());
+    }
+
+    #[test]
+    fn strict_mode_reports_missing_metadata() {
+        match Replay::from_bytes(orders()) {
+            Err(ReplayError::NoMetadata) => {},
+            Err(e) => panic!("expected NoMetadata, got {:?}", e),
+            Ok(_) => panic!("expected NoMetadata, the replay was read")
+        }
+    }
+
+    #[test]
+    fn skips_packets_of_unknown_kind() {
+        let unknown = packet(1, 2, &[0x99, 1, 2, 3]);
+        let bytes = [handshake(), packet(0, 1, &move_order()), unknown, packet(0, 3, &move_order()), trailer()].concat();
+        let replay = Replay::from_bytes(bytes).unwrap();
+        let mut orders = replay.orders();
+        assert_eq!(orders.by_ref().map(|order| order.unwrap().frame).collect::<Vec<_>>(), vec![0, 1, 3]);
+        let stats = orders.into_stats();
+        assert_eq!((stats.orders, stats.last_frame, stats.skipped_packets), (3, 3, 1));
+        assert!(stats.error.is_none());
+    }
+}
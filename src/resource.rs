@@ -0,0 +1,180 @@
+use openra_heatmap::{Bounds, MapGridType};
+use regex::Regex;
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use serde_json::Value;
+use std::io::Error;
+use std::thread;
+use std::time::Duration;
+
+pub const DEFAULT_URL: &str = "https://resource.openra.net";
+
+/// Talks to the OpenRA resource site, or to a mirror of it such as `openra-heatmap serve`.
+/// All requests share one HTTP client; failed requests are retried a few times with an increasing delay.
+pub struct ResourceClient {
+    base_url: String,
+    client: Client,
+    retries: u32
+}
+
+/// What the resource site knows about a map.
+pub struct MapInfo {
+    pub id: u32,
+    pub bounds: Bounds,
+    pub grid: MapGridType,
+    /// Screenshots listed in the map record, if the resource site lists them.
+    pub screenshots: Vec<u32>
+}
+
+fn construct_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(ACCEPT, HeaderValue::from_str("*/*").unwrap());
+    headers
+}
+
+impl ResourceClient {
+    pub fn new(base_url: &str, timeout: Duration, retries: u32) -> Result<Self, Error> {
+        let client = Client::builder()
+            .default_headers(construct_headers())
+            .timeout(timeout)
+            .build()
+            .map_err(Error::other)?;
+        Ok(ResourceClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+            retries
+        })
+    }
+
+    /// Fetches a path of the resource site. Connection problems and server errors are retried,
+    /// other failures (such as 404 Not Found) are reported right away.
+    fn get(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let url = format!("{}{}", self.base_url, path);
+        let mut attempt = 0;
+        loop {
+            let error = match self.client.get(&url).send() {
+                Ok(response) if response.status().is_success() => {
+                    return response.bytes().map(|content| content.to_vec()).map_err(|e| Error::other(format!("{}: {}", url, e)));
+                },
+                Ok(response) if !response.status().is_server_error() => {
+                    return Err(Error::other(format!("{}: {}", url, response.status())));
+                },
+                Ok(response) => response.status().to_string(),
+                Err(e) => e.to_string()
+            };
+            if attempt == self.retries {
+                return Err(Error::other(format!("{}: {} (tried {} times)", url, error, attempt + 1)));
+            }
+            attempt += 1;
+            println!("Could not fetch {} ({}), retrying", url, error);
+            thread::sleep(Duration::from_millis(500 << attempt));
+        }
+    }
+
+    /// The map record as JSON. Only returned when it is understood by `parse_map_info`.
+    pub fn map_info(&self, hash: &str) -> Result<Vec<u8>, Error> {
+        let content = self.get(&format!("/map/hash/{}", hash))?;
+        parse_map_info(&content)?;
+        Ok(content)
+    }
+
+    /// A picture of the map to draw on. In order of preference: a screenshot listed in the map record,
+    /// the minimap preview the resource site renders for every map, or a screenshot linked from the map's page.
+    pub fn map_picture(&self, map_info: &MapInfo) -> Result<Vec<u8>, Error> {
+        let mut failures = Vec::new();
+        match map_info.screenshots.first() {
+            Some(&screenshot_id) => match self.image(&format!("/screenshots/{}", screenshot_id)) {
+                Ok(picture) => return Ok(picture),
+                Err(e) => failures.push(format!("screenshot {} from the map record: {}", screenshot_id, e))
+            },
+            None => failures.push("the map record lists no screenshots".to_string())
+        }
+        match self.image(&format!("/maps/{}/minimap", map_info.id)) {
+            Ok(picture) => return Ok(picture),
+            Err(e) => failures.push(format!("minimap: {}", e))
+        }
+        match self.scrape_screenshot_id(map_info.id) {
+            Ok(Some(screenshot_id)) => match self.image(&format!("/screenshots/{}", screenshot_id)) {
+                Ok(picture) => return Ok(picture),
+                Err(e) => failures.push(format!("screenshot {} from the map page: {}", screenshot_id, e))
+            },
+            Ok(None) => failures.push("the map page links to no screenshot".to_string()),
+            Err(e) => failures.push(format!("map page: {}", e))
+        }
+        Err(Error::other(format!("found no picture of map {}: {}", map_info.id, failures.join("; "))))
+    }
+
+    /// Fetches a path that should be an image, rather than e.g. an HTML error page.
+    fn image(&self, path: &str) -> Result<Vec<u8>, Error> {
+        println!("Downloading {}{}", self.base_url, path);
+        let content = self.get(path)?;
+        image::guess_format(&content).map_err(|_| Error::other(format!("{}{} is not an image", self.base_url, path)))?;
+        Ok(content)
+    }
+
+    // only for maps whose record does not list the screenshots yet, see https://github.com/OpenRA/OpenRA-Resources/pull/365
+    fn scrape_screenshot_id(&self, map_id: u32) -> Result<Option<u32>, Error> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"/screenshots/(\d+)/").unwrap();
+        }
+        let page = self.get(&format!("/maps/{}", map_id))?;
+        let page = String::from_utf8_lossy(&page);
+        Ok(RE.captures(&page).and_then(|caps| caps[1].parse::<u32>().ok()))
+    }
+
+    pub fn map_package(&self, map_id: u32) -> Result<Vec<u8>, Error> {
+        println!("Downloading map {}", map_id);
+        self.get(&format!("/maps/{}/oramap", map_id))
+    }
+}
+
+pub fn parse_map_info(json: &[u8]) -> Result<MapInfo, Error> {
+    let map_info: Value = serde_json::from_slice(json).map_err(Error::other)?;
+    let object = &map_info[0];
+    let id = object["id"].as_u64().ok_or_else(|| Error::other("Map is not known on the resource site"))? as u32;
+    let height = object["height"].as_str().and_then(|h| h.parse::<u16>().ok()).ok_or_else(|| Error::other("cannot parse height"))?;
+    let width = object["width"].as_str().and_then(|w| w.parse::<u16>().ok()).ok_or_else(|| Error::other("cannot parse width"))?;
+    let bounds = object["bounds"].as_str()
+        .and_then(|b| {
+            let b: Vec<i32> = b.split(',').filter_map(|n| n.trim().parse().ok()).collect();
+            match b.as_slice() {
+                [x, y, width, height] => Some(Bounds { x: *x, y: *y, width: *width, height: *height }),
+                _ => None
+            }
+        })
+        .unwrap_or(Bounds { x: 0, y: 0, width: width as i32, height: height as i32 });
+    let grid = match object["map_grid_type"].as_str() {
+        Some("RectangularIsometric") => MapGridType::RectangularIsometric,
+        _ => MapGridType::Rectangular
+    };
+
+    let mut screenshots = Vec::new();
+    for field in &["screenshots", "screenshot"] {
+        screenshot_ids(&object[*field], &mut screenshots);
+    }
+
+    Ok(MapInfo {
+        id,
+        bounds,
+        grid,
+        screenshots
+    })
+}
+
+/// Screenshots may be listed by id, by URL or as objects with an id or URL.
+fn screenshot_ids(value: &Value, ids: &mut Vec<u32>) {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(r"/screenshots/(\d+)").unwrap();
+    }
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| screenshot_ids(item, ids)),
+        Value::Object(_) => {
+            screenshot_ids(&value["id"], ids);
+            screenshot_ids(&value["url"], ids);
+        },
+        Value::Number(id) => ids.extend(id.as_u64().map(|id| id as u32)),
+        Value::String(s) => ids.extend(s.parse::<u32>().ok().or_else(|| RE.captures(s).and_then(|caps| caps[1].parse().ok()))),
+        _ => {}
+    }
+    ids.dedup();
+}
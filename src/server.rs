@@ -0,0 +1,58 @@
+use crate::cache::{self, MapCache};
+use crate::resource::parse_map_info;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::io::Error;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Serves the maps in the cache the way the resource site does, as far as `ResourceClient` needs it.
+/// The cached picture of a map is served as its minimap.
+/// Fill the cache on a machine that is online, then serve it to air-gapped ones or use it as a test fixture.
+pub fn serve(cache: MapCache, address: SocketAddr) -> Result<(), Error> {
+    let dir = cache.dir().display().to_string();
+    let cache = Arc::new(cache);
+    let mut runtime = tokio::runtime::Builder::new().basic_scheduler().enable_all().build()?;
+    runtime.block_on(async move {
+        let make_service = make_service_fn(move |_| {
+            let cache = cache.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let cache = cache.clone();
+                    async move { Ok::<_, Infallible>(respond(&cache, request.uri().path())) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&address).map_err(Error::other)?.serve(make_service);
+        println!("Serving the maps cached in {} on http://{}", dir, server.local_addr());
+        server.await.map_err(Error::other)
+    })
+}
+
+fn respond(cache: &MapCache, path: &str) -> Response<Body> {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let file = match segments.as_slice() {
+        ["map", "hash", hash] => cache.read(hash, cache::MAP_JSON),
+        ["maps", id, "minimap"] => map_hash(cache, id).and_then(|hash| cache.read(&hash, cache::PICTURE)),
+        ["maps", id, "oramap"] => map_hash(cache, id).and_then(|hash| cache.read(&hash, cache::MAP_PACKAGE)),
+        _ => None
+    };
+    println!("GET {} {}", path, if file.is_some() { "200" } else { "404" });
+    match file {
+        Some(contents) => Response::new(Body::from(contents)),
+        None => {
+            let mut response = Response::new(Body::from("Not Found"));
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        }
+    }
+}
+
+/// The hash of the map with the given resource site id.
+fn map_hash(cache: &MapCache, id: &str) -> Option<String> {
+    let id = id.parse::<u32>().ok()?;
+    cache.list().ok()?.into_iter()
+        .map(|map| map.hash)
+        .find(|hash| cache.read(hash, cache::MAP_JSON).is_some_and(|json| parse_map_info(&json).is_ok_and(|info| info.id == id)))
+}
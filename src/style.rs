@@ -0,0 +1,126 @@
+use image::Rgba;
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::io::Error;
+use crate::markers::{MarkerKind, Shape};
+use crate::minimap::parse_color;
+
+const DEFAULT_STYLE: &str = include_str!("../default-style.toml");
+
+/// Which kind of location a style applies to.
+#[derive(Deserialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum TargetKind {
+    Cell,
+    Position,
+    Actor
+}
+
+/// A style as written in the config file, see default-style.toml.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleEntry {
+    #[serde(default)]
+    orders: Vec<String>,
+    target: Option<TargetKind>,
+    #[serde(default = "included")]
+    include: bool,
+    label: Option<String>,
+    color: Option<String>,
+    #[serde(default)]
+    shape: Shape,
+    #[serde(default = "default_size")]
+    size: u32
+}
+
+fn included() -> bool {
+    true
+}
+
+fn default_size() -> u32 {
+    9
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StyleFile {
+    #[serde(default)]
+    style: Vec<StyleEntry>
+}
+
+/// How the orders matching a style are drawn.
+pub struct Style {
+    patterns: Vec<Regex>,
+    target: Option<TargetKind>,
+    pub include: bool,
+    pub label: Option<String>,
+    pub color: Rgba<u8>,
+    pub shape: Shape,
+    pub size: u32
+}
+
+impl Style {
+    fn matches(&self, order: &str, kind: MarkerKind) -> bool {
+        let target = match kind {
+            MarkerKind::Cell => TargetKind::Cell,
+            MarkerKind::Position => TargetKind::Position,
+            MarkerKind::Actor(_) => TargetKind::Actor
+        };
+        self.target.is_none_or(|wanted| wanted == target)
+            && (self.patterns.is_empty() || self.patterns.iter().any(|pattern| pattern.is_match(order)))
+    }
+
+    /// Whether the style applies to orders targeting an actor, which are not plotted with --actor-targets none.
+    pub fn targets_actors(&self) -> bool {
+        self.target == Some(TargetKind::Actor)
+    }
+}
+
+/// The styles orders are drawn with, the first matching style applies.
+pub struct StyleSheet {
+    pub styles: Vec<Style>
+}
+
+impl StyleSheet {
+    pub fn builtin() -> Self {
+        StyleSheet::parse(DEFAULT_STYLE).expect("default-style.toml is valid")
+    }
+
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)?;
+        StyleSheet::parse(&contents).map_err(|e| Error::other(format!("Could not read style {}: {}", path, e)))
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let file: StyleFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let mut styles = Vec::new();
+        for entry in file.style {
+            let patterns = entry.orders.iter()
+                .map(|pattern| {
+                    let parts: Vec<String> = pattern.split('*').map(regex::escape).collect();
+                    Regex::new(&format!("^{}$", parts.join(".*"))).map_err(|e| e.to_string())
+                })
+                .collect::<Result<_, _>>()?;
+            let color = match &entry.color {
+                Some(color) => parse_color(color).ok_or_else(|| format!("{} is not a color", color))?,
+                None => Rgba([255, 255, 255, 255])
+            };
+            styles.push(Style {
+                patterns,
+                target: entry.target,
+                include: entry.include,
+                label: entry.label,
+                color,
+                shape: entry.shape,
+                size: entry.size
+            });
+        }
+        Ok(StyleSheet { styles })
+    }
+
+    /// The style of an order, None when no style matches it.
+    pub fn style(&self, order: &str, kind: MarkerKind) -> Option<&Style> {
+        self.styles.iter().find(|style| style.matches(order, kind))
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::{BTreeMap, HashMap};
+use crate::error::ReplayError;
+use crate::order::OrderType;
+use crate::packet::Packets;
+use crate::replay::ReadMode;
+
+/// The state of the game world a client reported for a frame. Clients whose hashes differ for a frame are out of sync.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SyncHash {
+    pub client: i32,
+    pub frame: i32,
+    pub hash: i32,
+    /// Bit `i` is set once the `i`-th player of the game world (OpenRA's `World.Players`, which also holds
+    /// non-playing players like Neutral) has lost. None for releases that did not report it.
+    pub defeat_state: Option<u64>
+}
+
+impl SyncHash {
+    pub fn is_defeated(&self, player_index: u32) -> bool {
+        self.defeat_state.is_some_and(|state| player_index < 64 && state & (1 << player_index) != 0)
+    }
+}
+
+/// Iterates over the sync hashes in a replay. Like `Orders`, the iteration ends after the first error,
+/// which is only returned when not salvaging.
+pub struct SyncHashes<'a> {
+    packets: Packets<'a>,
+    mode: ReadMode,
+    done: bool
+}
+
+impl<'a> SyncHashes<'a> {
+    pub(crate) fn new(data: &'a [u8], mode: ReadMode) -> Self {
+        SyncHashes {
+            packets: Packets::new(data, mode),
+            mode,
+            done: false
+        }
+    }
+
+    fn read_next(&mut self) -> Result<Option<SyncHash>, ReplayError> {
+        while let Some(packet) = self.packets.next_packet()? {
+            let reader = &mut self.packets.reader;
+            if packet.kind != Some(Ok(OrderType::SyncHash)) || packet.end - reader.pos() < 9 {
+                self.packets.skip(&packet)?;
+                continue;
+            }
+            let frame = reader.read_i32()?;
+            reader.read_u8()?;
+            let hash = reader.read_i32()?;
+            // releases before the defeat state was added only sent the hash
+            let defeat_state = if packet.end - reader.pos() >= 8 { Some(reader.read_u64()?) } else { None };
+            self.packets.skip(&packet)?;
+            return Ok(Some(SyncHash { client: packet.client, frame, hash, defeat_state }));
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for SyncHashes<'a> {
+    type Item = Result<SyncHash, ReplayError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.read_next() {
+            Ok(Some(sync_hash)) => Some(Ok(sync_hash)),
+            Ok(None) => {
+                self.done = true;
+                None
+            },
+            Err(e) => {
+                self.done = true;
+                if self.mode == ReadMode::Salvage { None } else { Some(Err(e)) }
+            }
+        }
+    }
+}
+
+/// The first frame each player was reported defeated at, by the player's index in the game world (see `SyncHash::defeat_state`).
+pub fn defeats(sync_hashes: &[SyncHash]) -> BTreeMap<u32, i32> {
+    let mut defeats = BTreeMap::new();
+    for sync_hash in sync_hashes {
+        for player_index in (0..64).filter(|&index| sync_hash.is_defeated(index)) {
+            let frame = defeats.entry(player_index).or_insert(sync_hash.frame);
+            *frame = (*frame).min(sync_hash.frame);
+        }
+    }
+    defeats
+}
+
+/// The first frame each client was reported defeated at, when the defeat state can be matched to the clients.
+///
+/// The game world's players start with the map's non-playable players, such as Neutral, whose number only the map tells.
+/// Next come the players of `playing_clients` (see `Lobby::playing_clients`). That number is found by matching the defeats
+/// to the clients that `lost` (see `Outcome::Lost`); None when no or more than one number matches.
+pub fn defeated_clients(sync_hashes: &[SyncHash], playing_clients: &[i32], lost: &[i32]) -> Option<BTreeMap<i32, i32>> {
+    let defeats = defeats(sync_hashes);
+    let clients_of = |first: u32| -> Option<BTreeMap<i32, i32>> {
+        defeats.iter()
+            .map(|(&index, &frame)| Some((*playing_clients.get(index.checked_sub(first)? as usize)?, frame)))
+            .collect()
+    };
+    let mut matches = (0..64).filter_map(clients_of).filter(|clients| {
+        clients.len() == lost.len() && lost.iter().all(|client| clients.contains_key(client))
+    });
+    match (matches.next(), matches.next()) {
+        (Some(clients), None) => Some(clients),
+        (Some(clients), Some(_)) if clients.is_empty() => Some(clients),
+        _ => None
+    }
+}
+
+/// The first frame for which clients reported different hashes. Pass the sync hashes of several replays
+/// of the same game to find where their clients went out of sync.
+pub fn first_desync(sync_hashes: &[SyncHash]) -> Option<i32> {
+    let mut hashes: HashMap<i32, i32> = HashMap::new();
+    let mut desync: Option<i32> = None;
+    for sync_hash in sync_hashes {
+        let hash = *hashes.entry(sync_hash.frame).or_insert(sync_hash.hash);
+        if hash != sync_hash.hash && desync.is_none_or(|frame| sync_hash.frame < frame) {
+            desync = Some(sync_hash.frame);
+        }
+    }
+    desync
+}
@@ -0,0 +1,149 @@
+use std::collections::{HashMap, VecDeque};
+use crate::map::MapGridType;
+use crate::order::{CPos, Order, Target};
+
+/// Orders that send their subject (and the actors grouped with it) to the targeted location.
+const MOVE_ORDERS: &[&str] = &["Move", "AttackMove", "AssaultMove", "Harvest"];
+/// Orders that are only ever issued to buildings.
+const BUILDING_ORDERS: &[&str] = &["Sell", "RepairBuilding", "PowerDown", "SetRallyPoint", "PrimaryProducer"];
+
+/// How an actor's position was found out. Neither is certain: there is no simulation of the game behind it.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Confidence {
+    /// A building placed there: the only placement of the building's owner that no building was matched to yet.
+    /// Buildings don't move, but a building that was never placed, like a construction yard deployed from an MCV,
+    /// still takes the placement of another one.
+    Placement,
+    /// The oldest of several placements of the building's owner that no building was matched to yet. Less certain:
+    /// a placed building that is never given an order, or one that was never placed, shifts every later guess by one.
+    PlacementGuess,
+    /// The last cell the actor was ordered to. It may not have arrived yet, or may have moved on since.
+    Destination
+}
+
+/// The last known position of an actor.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TrackedPosition {
+    pub cell: CPos,
+    pub confidence: Confidence,
+    /// The frame of the order this position was learned from.
+    pub frame: i32
+}
+
+/// Keeps an approximate position of every actor, from the orders of a replay as they are read,
+/// so orders targeting an actor can be placed on the map.
+///
+/// ```no_run
+/// # use openra_heatmap::{ActorTracker, MapGridType, Replay};
+/// let replay = Replay::open("game.orarep").unwrap();
+/// let mut tracker = ActorTracker::new(MapGridType::Rectangular);
+/// for order in replay.orders() {
+///     let order = order.unwrap();
+///     if let Some(position) = tracker.resolve(&order.target) {
+///         println!("{} at {},{} ({:?})", order.order, position.cell.x, position.cell.y, position.confidence);
+///     }
+///     tracker.observe(&order);
+/// }
+/// ```
+pub struct ActorTracker {
+    grid: MapGridType,
+    positions: HashMap<u32, TrackedPosition>,
+    /// Per client, the placements not yet matched to a building.
+    placements: HashMap<i32, VecDeque<(CPos, i32)>>
+}
+
+impl ActorTracker {
+    pub fn new(grid: MapGridType) -> Self {
+        ActorTracker {
+            grid,
+            positions: HashMap::new(),
+            placements: HashMap::new()
+        }
+    }
+
+    /// Learns from an order what it tells about the positions of actors.
+    pub fn observe(&mut self, order: &Order) {
+        if order.order == "PlaceBuilding" {
+            if let Some(cell) = order.target_location(self.grid) {
+                self.placements.entry(order.client).or_default().push_back((cell, order.frame));
+            }
+        } else if MOVE_ORDERS.contains(&order.order.as_str()) {
+            if let Some(cell) = order.target_location(self.grid) {
+                let position = TrackedPosition { cell, confidence: Confidence::Destination, frame: order.frame };
+                let grouped = order.grouped.iter().flatten();
+                for &actor in order.subject.iter().chain(grouped) {
+                    self.positions.insert(actor, position);
+                }
+            }
+        } else if BUILDING_ORDERS.contains(&order.order.as_str()) {
+            let subject = match order.subject {
+                Some(subject) if !self.positions.contains_key(&subject) => subject,
+                _ => return
+            };
+            // the first order given to a building we don't know yet: assume it is the oldest unmatched building of its owner
+            let placements = match self.placements.get_mut(&order.client) {
+                Some(placements) => placements,
+                None => return
+            };
+            let confidence = if placements.len() == 1 { Confidence::Placement } else { Confidence::PlacementGuess };
+            if let Some((cell, frame)) = placements.pop_front() {
+                self.positions.insert(subject, TrackedPosition { cell, confidence, frame });
+            }
+        }
+    }
+
+    pub fn position(&self, actor: u32) -> Option<TrackedPosition> {
+        self.positions.get(&actor).copied()
+    }
+
+    /// The position of the actor an order targets. Frozen actors (what a player remembers of
+    /// an enemy building under the fog) carry the id of the actor they show.
+    pub fn resolve(&self, target: &Target) -> Option<TrackedPosition> {
+        match *target {
+            Target::Actor(actor) => self.position(actor),
+            Target::FrozenActor { frozen_actor_id, .. } => self.position(frozen_actor_id),
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(frame: i32, name: &str, subject: u32, target: Target) -> Order {
+        Order {
+            frame,
+            client: 0,
+            order: name.to_string(),
+            immediate: false,
+            subject: Some(subject),
+            target,
+            target_string: None,
+            extra_actors: Vec::new(),
+            extra_location: None,
+            extra_data: None,
+            queued: false,
+            grouped: None
+        }
+    }
+
+    fn cell(x: i32, y: i32) -> Target {
+        Target::Cell { cell: CPos { x, y, layer: 0 }, subcell: 0 }
+    }
+
+    #[test]
+    fn only_the_last_placement_left_is_certain() {
+        let mut tracker = ActorTracker::new(MapGridType::Rectangular);
+        tracker.observe(&order(1, "PlaceBuilding", 1, cell(10, 10)));
+        tracker.observe(&order(2, "PlaceBuilding", 1, cell(20, 20)));
+        tracker.observe(&order(3, "Sell", 100, Target::Invalid));
+        tracker.observe(&order(4, "RepairBuilding", 101, Target::Invalid));
+        tracker.observe(&order(5, "PowerDown", 102, Target::Invalid));
+
+        let position = |actor| tracker.position(actor).map(|position| (position.cell.x, position.confidence));
+        assert_eq!(position(100), Some((10, Confidence::PlacementGuess)));
+        assert_eq!(position(101), Some((20, Confidence::Placement)));
+        assert_eq!(position(102), None);
+    }
+}
@@ -0,0 +1,44 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Stdio};
+
+const HASH: &str = "2e4de7bc8d2acd9e0cd6ed4ae5f09ae4e8bd4a3c";
+const MAP_JSON: &str = r#"[{"id": 12345, "title": "Behind The Veil", "map_hash": "2e4de7bc8d2acd9e0cd6ed4ae5f09ae4e8bd4a3c"}]"#;
+
+/// Starts `serve` on an ephemeral port over a cache with a single map, and fetches the map's information from it.
+#[test]
+fn serves_cached_map() {
+    let cache_dir = std::env::temp_dir().join(format!("openra-heatmap-serve-{}", std::process::id()));
+    fs::create_dir_all(cache_dir.join("maps").join(HASH)).unwrap();
+    fs::write(cache_dir.join("maps").join(HASH).join("map.json"), MAP_JSON).unwrap();
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_openra-heatmap"))
+        .arg("--cache-dir").arg(&cache_dir)
+        .args(["serve", "--listen", "127.0.0.1:0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    // "Serving the maps cached in <dir> on http://<address>"
+    let address = line.trim().rsplit("http://").next().unwrap().to_string();
+
+    let get = |path: &str| {
+        let mut stream = TcpStream::connect(&address).unwrap();
+        write!(stream, "GET {} HTTP/1.0\r\nHost: {}\r\n\r\n", path, address).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let found = get(&format!("/map/hash/{}", HASH));
+    let missing = get("/map/hash/0000");
+    server.kill().unwrap();
+    server.wait().unwrap();
+    fs::remove_dir_all(&cache_dir).unwrap();
+
+    assert!(found.starts_with("HTTP/1.0 200"), "{}", found);
+    assert!(found.ends_with(MAP_JSON), "{}", found);
+    assert!(missing.starts_with("HTTP/1.0 404"), "{}", missing);
+}